@@ -0,0 +1,123 @@
+//! Runs a level headlessly with a given set of attractor placements
+//!
+//! Usage: simulate [--project PATH] [--tuning PATH] [--ticks N] LEVEL [X,Y[:ITEM] ...]
+//!        simulate [--project PATH] [--tuning PATH] [--ticks N] --replay FILE
+//!
+//! Positions are world coordinates, the same ones the placer uses in game.
+//! ITEM names a catalog entry such as `scraps` or `fish` and defaults to a hot dog.
+//! A replay file recorded by the game brings its own level, placements and raccoon.
+
+use std::process::ExitCode;
+
+use bevy::math::Vec2;
+use ld55::components::LoseCause;
+use ld55::items::{ItemKind, Placement};
+use ld55::sim::{self, Outcome, Simulation};
+
+const DEFAULT_PROJECT: &str = "assets/attic.ldtk";
+const DEFAULT_TUNING: &str = "assets/game.tuning.ron";
+const DEFAULT_TICK_LIMIT: u32 = 60 * 60;
+
+fn usage() -> ExitCode {
+    eprintln!("usage: simulate [--project PATH] [--tuning PATH] [--ticks N] LEVEL [X,Y[:ITEM] ...]");
+    eprintln!("       simulate [--project PATH] [--tuning PATH] [--ticks N] --replay FILE");
+    ExitCode::from(2)
+}
+
+fn parse_placement(arg: &str) -> Option<Placement> {
+    let (pos, kind) = match arg.split_once(':') {
+        Some((pos, kind)) => (pos, ItemKind::from_name(kind)?),
+        None => (arg, ItemKind::default()),
+    };
+    let (x, y) = pos.split_once(',')?;
+    Some(Placement {
+        pos: Vec2::new(x.trim().parse().ok()?, y.trim().parse().ok()?),
+        kind,
+    })
+}
+
+fn main() -> ExitCode {
+    let mut project_path = DEFAULT_PROJECT.to_string();
+    let mut tuning_path = DEFAULT_TUNING.to_string();
+    let mut replay_path = None;
+    let mut tick_limit = DEFAULT_TICK_LIMIT;
+    let mut level = None;
+    let mut attractors = Vec::new();
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--project" => match args.next() {
+                Some(path) => project_path = path,
+                None => return usage(),
+            },
+            "--tuning" => match args.next() {
+                Some(path) => tuning_path = path,
+                None => return usage(),
+            },
+            "--replay" => match args.next() {
+                Some(path) => replay_path = Some(path),
+                None => return usage(),
+            },
+            "--ticks" => match args.next().and_then(|n| n.parse().ok()) {
+                Some(n) => tick_limit = n,
+                None => return usage(),
+            },
+            _ if level.is_none() => match arg.parse::<usize>() {
+                Ok(n) => level = Some(n),
+                Err(_) => return usage(),
+            },
+            _ => match parse_placement(&arg) {
+                Some(pos) => attractors.push(pos),
+                None => return usage(),
+            },
+        }
+    }
+    if level.is_some() == replay_path.is_some() {
+        return usage();
+    }
+
+    let report = sim::load_project(&project_path).and_then(|project| {
+        let tunables = sim::load_tunables(&tuning_path)?;
+        let mut simulation = match (&replay_path, level) {
+            (Some(path), _) => {
+                let replay = sim::load_replay(path)?;
+                if replay.tunables_version != tunables.version {
+                    eprintln!(
+                        "simulate: replay was recorded with tuning version {}, running with {}",
+                        replay.tunables_version, tunables.version
+                    );
+                }
+                Simulation::from_replay(&project, &replay)?
+            }
+            (None, Some(level)) => Simulation::new(&project, level, &attractors)?,
+            (None, None) => unreachable!(),
+        };
+        simulation.set_tunables(tunables);
+        Ok(simulation.run(tick_limit))
+    });
+    match report {
+        Ok(report) => {
+            let outcome = match report.outcome {
+                Outcome::Win => "win",
+                Outcome::Lose(LoseCause::Starved) => "starved",
+                Outcome::Lose(LoseCause::Caught) => "caught",
+                Outcome::Lose(LoseCause::TimeUp) => "time-up",
+                Outcome::TickLimit => "tick-limit",
+            };
+            print!(
+                "outcome={} ticks={} attractors_consumed={}",
+                outcome, report.ticks, report.attractors_consumed
+            );
+            match report.score {
+                Some(score) => println!(" score={} stars={}", score.points, score.stars),
+                None => println!(),
+            }
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            eprintln!("simulate: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,108 @@
+//! The main camera: panning around while planning, following the raccoon otherwise
+
+use bevy::prelude::*;
+use bevy_ecs_ldtk::prelude::*;
+
+use crate::components::*;
+use crate::level::loaded_project;
+use crate::systems;
+use crate::tuning::Tunables;
+
+/// Spawns the main camera, centres it on each level's `Focus` entity and keeps track of the mouse in world space
+pub struct CameraPlugin;
+
+impl Plugin for CameraPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<WorldMouse>()
+            .register_ldtk_entity::<CameraFocusBundle>("Focus")
+            .add_systems(Startup, spawn_camera)
+            .add_systems(OnEnter(GameState::Planning), zoom_for_planning)
+            .add_systems(Update, (
+                systems::camera_follow.run_if(not(in_state(GameState::Planning))),
+                systems::mouse_to_world,
+                focus_camera.run_if(in_state(GameState::Focusing)),
+                pan_camera.run_if(in_state(GameState::Planning)),
+                // so retuning the zoom shows without leaving planning
+                zoom_for_planning.run_if(in_state(GameState::Planning).and_then(resource_changed::<Tunables>())),
+            ));
+    }
+}
+
+fn spawn_camera(
+    mut commands: Commands,
+) {
+    let camera = Camera2dBundle::default();
+    commands.spawn(camera).insert(MainCamera);
+}
+
+/// Waits for the level's camera focus to spawn, then decides whether there's anything left to plan
+fn focus_camera(
+    mut next_state: ResMut<NextState<GameState>>,
+    level: Res<LevelSelection>,
+    projects: Query<&Handle<LdtkProject>>,
+    project_assets: Res<Assets<LdtkProject>>,
+    focus: Query<&GlobalTransform, With<CameraFocus>>,
+    mut camera: Query<&mut Transform, With<MainCamera>>,
+) {
+    let Some(project) = loaded_project(&projects, &project_assets) else {
+        return;
+    };
+    let victory = project
+        .find_raw_level_by_level_selection(&level)
+        .is_some_and(is_victory_room);
+    for focus in &focus {
+        for mut camera in camera.iter_mut() {
+            if focus.translation() != Vec3::new(0.0, 0.0, 0.0) {
+                camera.translation = focus.translation();
+                if victory {
+                    next_state.set(GameState::GameWin);
+                } else {
+                    next_state.set(GameState::Planning);
+                }
+            }
+        }
+    }
+}
+
+#[derive(Component, Default, Clone)]
+struct CameraFocus;
+
+#[derive(Bundle, LdtkEntity, Default, Clone)]
+struct CameraFocusBundle {
+    focus: CameraFocus,
+    transform: TransformBundle,
+    #[from_entity_instance]
+    entity_instance: EntityInstance,
+}
+
+fn zoom_for_planning(
+    tunables: Res<Tunables>,
+    mut camera: Query<&mut OrthographicProjection, With<MainCamera>>,
+) {
+    for mut proj in camera.iter_mut() {
+        proj.scale = tunables.planning_zoom;
+    }
+}
+
+/// WASD moves the camera around the level while planning
+fn pan_camera(
+    time: Res<Time>,
+    tunables: Res<Tunables>,
+    keyboard_input: Res<Input<KeyCode>>,
+    mut camera: Query<&mut Transform, With<MainCamera>>,
+) {
+    let mut camera = camera.single_mut();
+    let camera_speed = tunables.camera_speed;
+    if keyboard_input.pressed(KeyCode::W) {
+        camera.translation.y += camera_speed * time.delta_seconds();
+    }
+    if keyboard_input.pressed(KeyCode::S) {
+        camera.translation.y -= camera_speed * time.delta_seconds();
+    }
+    if keyboard_input.pressed(KeyCode::D) {
+        camera.translation.x += camera_speed * time.delta_seconds();
+    }
+    if keyboard_input.pressed(KeyCode::A) {
+        camera.translation.x -= camera_speed * time.delta_seconds();
+    }
+}
@@ -1,25 +1,198 @@
+use std::collections::HashMap;
+
 use bevy::prelude::*;
+use bevy_ecs_ldtk::ldtk::Level;
 use bevy_ecs_ldtk::prelude::*;
+use bevy_ecs_ldtk::utils::{ldtk_pixel_coords_to_translation, ldtk_pixel_coords_to_translation_pivoted};
 
 use bevy_rapier2d::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::items::ItemKind;
+use crate::nav::Memory;
+use crate::physics;
+use crate::tuning::Tunables;
 
 
 
-#[derive(Default, PartialEq)]
+#[derive(States, Default, Clone, Copy, Eq, PartialEq, Hash, Debug)]
 pub enum GameState {
+    /// The title screen
     #[default]
+    MainMenu,
+    LevelSelect,
+    Settings,
+    Credits,
+    /// How to play, shown before a new game starts
     Instructions,
     Focusing,
     Planning,
     Running,
     AdvanceLevel,
+    /// Showing how the level just cleared went, before moving on
+    Results,
     GameWin,
     WinDance,
     GameLose,
 }
 
-#[derive(Resource, Default)]
-pub struct CurrentState(pub GameState);
+impl GameState {
+    /// Whether this is one of the screens shown outside of a level
+    pub fn is_menu(&self) -> bool {
+        matches!(
+            self,
+            GameState::MainMenu | GameState::LevelSelect | GameState::Settings | GameState::Credits | GameState::Instructions
+        )
+    }
+}
+
+/// Why the last run ended in [`GameState::GameLose`]
+#[derive(Resource, Copy, Clone, Eq, PartialEq, Debug, Default)]
+pub enum LoseCause {
+    #[default]
+    Starved,
+    Caught,
+    /// The level's time limit ran out before the raccoon reached the goal
+    TimeUp,
+}
+
+/// Per-level puzzle tuning, read from the LDtk level's custom fields
+#[derive(Resource, Clone, Debug, PartialEq)]
+pub struct LevelRules {
+    /// Goodies the placer starts the level with
+    pub budget: Inventory,
+    /// Placements a tidy solution needs
+    pub par: Option<u32>,
+    /// Seconds the raccoon has to reach the goal once running
+    pub time_limit: Option<f32>,
+    /// Items the placer may put down here
+    pub allowed: Vec<ItemKind>,
+    /// How far and how wide the raccoon can see
+    pub vision: Vision,
+    /// Whether the raccoon finds its way around walls to attractors it has lost sight of
+    pub pathfinding: bool,
+    /// Seconds a full hunger meter lasts before the raccoon starves
+    pub hunger_time: f32,
+}
+
+impl Default for LevelRules {
+    fn default() -> Self {
+        LevelRules {
+            budget: Inventory::new(&[
+                (ItemKind::Scraps, 5),
+                (ItemKind::HotDog, 10),
+                (ItemKind::Fish, 3),
+            ]),
+            par: None,
+            time_limit: None,
+            allowed: ItemKind::ALL.to_vec(),
+            vision: Vision::default(),
+            pathfinding: false,
+            hunger_time: 10.0,
+        }
+    }
+}
+
+impl LevelRules {
+    /// Reads the rules from a level's fields, keeping the defaults for any field it doesn't have
+    pub fn from_level(level: &Level) -> Self {
+        let mut rules = LevelRules::default();
+        for (kind, field) in [
+            (ItemKind::Scraps, "ScrapsBudget"),
+            (ItemKind::HotDog, "HotDogBudget"),
+            (ItemKind::Fish, "FishBudget"),
+        ] {
+            if let Ok(count) = level.get_int_field(field) {
+                rules.budget.set(kind, (*count).max(0) as u32);
+            }
+        }
+        if let Ok(par) = level.get_int_field("Par") {
+            rules.par = (*par > 0).then_some(*par as u32);
+        }
+        if let Ok(time_limit) = level.get_float_field("TimeLimit") {
+            rules.time_limit = (*time_limit > 0.0).then_some(*time_limit);
+        }
+        if let Ok(allowed) = level.iter_enums_field("AllowedItems") {
+            let allowed: Vec<ItemKind> = allowed.filter_map(|name| ItemKind::from_name(name)).collect();
+            // an empty list leaves every item available
+            if !allowed.is_empty() {
+                rules.allowed = allowed;
+            }
+        }
+        if let Ok(radius) = level.get_float_field("SightRadius") {
+            if *radius > 0.0 {
+                rules.vision.radius = *radius;
+            }
+        }
+        if let Ok(cone) = level.get_float_field("ViewCone") {
+            rules.vision.cone = (*cone > 0.0 && *cone < 360.0).then_some(cone.to_radians());
+        }
+        if let Ok(pathfinding) = level.get_bool_field("Pathfinding") {
+            rules.pathfinding = *pathfinding;
+        }
+        if let Ok(hunger_time) = level.get_float_field("HungerTime") {
+            if *hunger_time > 0.0 {
+                rules.hunger_time = *hunger_time;
+            }
+        }
+        rules
+    }
+
+    pub fn allows(&self, kind: ItemKind) -> bool {
+        self.allowed.contains(&kind)
+    }
+}
+
+/// How far and how wide the raccoon can see
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Vision {
+    /// Anything further away than this is out of sight, in pixels
+    pub radius: f32,
+    /// Full width of the view cone around the way the raccoon faces, in radians, or `None` to see all around
+    pub cone: Option<f32>,
+}
+
+impl Default for Vision {
+    fn default() -> Self {
+        Vision {
+            radius: f32::INFINITY,
+            cone: None,
+        }
+    }
+}
+
+impl Vision {
+    /// Whether something at `offset` from the raccoon is within range and inside the cone, ignoring walls
+    pub fn covers(&self, facing: Vec2, offset: Vec2) -> bool {
+        if offset.length() > self.radius {
+            return false;
+        }
+        match self.cone {
+            Some(cone) if offset != Vec2::ZERO => facing.angle_between(offset).abs() <= cone / 2.0,
+            _ => true,
+        }
+    }
+}
+
+/// Reaching a level with its `Victory` field set wins the game
+pub fn is_victory_room(level: &Level) -> bool {
+    matches!(level.get_bool_field("Victory"), Ok(true))
+}
+
+/// Time spent in the current run, checked against [`LevelRules::time_limit`]
+#[derive(Resource, Clone, Debug, Default)]
+pub struct RunClock(pub f32);
+
+/// Marks a raccoon that has nothing in view or in mind to go for
+#[derive(Component, Copy, Clone, Debug, Default)]
+pub struct Stalled;
+
+/// What the raccoon got up to during the current run, for scoring
+#[derive(Resource, Clone, Debug, Default)]
+pub struct RunTally {
+    /// Attractors eaten that were part of the level rather than placed
+    pub garbage_eaten: u32,
+}
 
 #[derive(Copy, Clone, Eq, PartialEq, Debug, Default, Component)]
 pub struct Wall;
@@ -30,12 +203,13 @@ pub struct WallBundle {
 }
 
 
+/// Paces the goal or death animation, added when the level ends
 #[derive(Component, Clone)]
 pub struct LevelEndTimer(pub Timer);
 
-impl Default for LevelEndTimer {
-    fn default() -> Self {
-        LevelEndTimer(Timer::from_seconds(1.0, TimerMode::Once))
+impl LevelEndTimer {
+    pub fn from_seconds(seconds: f32) -> Self {
+        LevelEndTimer(Timer::from_seconds(seconds, TimerMode::Once))
     }
 }
 
@@ -44,13 +218,94 @@ pub struct AnimationTimer(pub Timer);
 
 impl Default for AnimationTimer {
     fn default() -> Self {
-        AnimationTimer(Timer::from_seconds(0.1, TimerMode::Repeating))
+        AnimationTimer(Timer::from_seconds(Tunables::default().animation_frame_seconds, TimerMode::Repeating))
     }
 }
 
 #[derive(Copy, Clone, Eq, PartialEq, Debug, Default, Component)]
 pub struct Player;
 
+/// The raccoon's progress, on the raccoon itself
+///
+/// [`LevelSnapshot`] carries it from one level's raccoon to the next until the game restarts.
+#[derive(Component, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct HeroStats {
+    pub level: u32,
+    pub hp: u32,
+    pub max_hp: u32,
+    pub xp: u32,
+    /// Hunger meter, full at 1.0 and empty at 0.0
+    pub hunger: f32,
+    /// Goodies won from baddies, handed to the placer when the next level starts
+    pub loot: Inventory,
+}
+
+/// How a scrap with a baddie turned out
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum FightResult {
+    /// The raccoon chased it off, possibly with a few scratches, and earned some loot
+    Won { loot: u32 },
+    Died,
+}
+
+impl Default for HeroStats {
+    fn default() -> Self {
+        HeroStats {
+            level: 1,
+            hp: 10,
+            max_hp: 10,
+            xp: 0,
+            hunger: 1.0,
+            loot: Inventory::default(),
+        }
+    }
+}
+
+impl HeroStats {
+    /// Experience needed to reach the next level
+    pub fn xp_to_next(&self) -> u32 {
+        self.level * 5
+    }
+
+    /// Better loot unlocks for the placer as the raccoon grows
+    pub fn loot_tier(&self) -> u32 {
+        self.level
+    }
+
+    pub fn gain_xp(&mut self, xp: u32) {
+        self.xp += xp;
+        while self.xp >= self.xp_to_next() {
+            self.xp -= self.xp_to_next();
+            self.level += 1;
+            self.max_hp += 2;
+            self.hp = self.max_hp;
+        }
+    }
+
+    /// Drains the hunger meter by `dt` seconds' worth, returning whether the raccoon has starved
+    pub fn hunger_pang(&mut self, dt: f32, rules: &LevelRules) -> bool {
+        self.hunger = (self.hunger - dt / rules.hunger_time).max(0.0);
+        self.hunger <= 0.0
+    }
+
+    /// Eating refills `refill` of the hunger meter and teaches the raccoon a little
+    pub fn eat(&mut self, refill: f32) {
+        self.hunger = (self.hunger + refill).min(1.0);
+        self.gain_xp(1);
+    }
+
+    /// Stronger baddies hit harder, but the bigger the gap the more the raccoon takes home
+    pub fn fight(&mut self, enemy_level: u32) -> FightResult {
+        let gap = enemy_level.saturating_sub(self.level);
+        self.hp = self.hp.saturating_sub(2 * gap + 1);
+        if self.hp == 0 {
+            return FightResult::Died;
+        }
+        self.gain_xp(2 * enemy_level);
+        FightResult::Won { loot: 1 + gap }
+    }
+}
+
 
 #[derive(Clone, Default, Bundle, LdtkEntity)]
 pub struct PlayerBundle {
@@ -66,7 +321,8 @@ pub struct PlayerBundle {
     #[grid_coords]
     grid_coords: GridCoords,
     animation_timer: AnimationTimer,
-    death_timer: LevelEndTimer,
+    memory: Memory,
+    stats: HeroStats,
 }
 
 #[derive(Component, Default, Clone)]
@@ -75,7 +331,6 @@ pub struct Goal;
 
 #[derive(Clone, Default, Bundle, LdtkEntity)]
 pub struct GoalBundle {
-    end_level_timer: LevelEndTimer,
     attractor: Attractor,
     goal: Goal,
     #[sprite_sheet_bundle]
@@ -99,23 +354,13 @@ pub struct ColliderBundle {
 }
 
 
+/// Builds the body listed for the entity in [`physics::PHYSICS`], if it has one
 impl From<&EntityInstance> for ColliderBundle {
     fn from(entity_instance: &EntityInstance) -> ColliderBundle {
-        let rotation_constraints = LockedAxes::ROTATION_LOCKED;
-
-        match entity_instance.identifier.as_ref() {
-            "Player" => ColliderBundle {
-                collider: Collider::cuboid(6., 14.),
-                rigid_body: RigidBody::Dynamic,
-                friction: Friction {
-                    coefficient: 0.0,
-                    combine_rule: CoefficientCombineRule::Min,
-                },
-                rotation_constraints,
-                ..Default::default()
-            },
-            _ => ColliderBundle::default(),
-        }
+        physics::spec(&entity_instance.identifier)
+            .and_then(|spec| spec.body)
+            .map(|body| body.collider_bundle())
+            .unwrap_or_default()
     }
 }
 
@@ -145,25 +390,53 @@ impl From<IntGridCell> for SensorBundle {
     }
 }
 
+/// Something the raccoon wants to eat
+#[derive(Copy, Clone, PartialEq, Debug, Component)]
+pub struct Attractor {
+    pub pull: f32,
+    pub radius: f32,
+    pub priority: f32,
+}
+
+impl Default for Attractor {
+    fn default() -> Self {
+        ItemKind::default().attractor()
+    }
+}
+
+/// An attractor the player placed, as opposed to one that came with the level
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Default, Component)]
+pub struct Goodie;
+
+/// Which catalog item a goodie, or the placer's selection, is
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Default, Component)]
+pub struct Item(pub ItemKind);
+
+/// The catalog item the placer puts down, or `None` when the level and loot tier allow nothing
 #[derive(Copy, Clone, Eq, PartialEq, Debug, Default, Component)]
-pub struct Attractor;
+pub struct Selected(pub Option<ItemKind>);
 
 
 #[derive(Clone, Default, Bundle, LdtkEntity)]
 pub struct GoodieBundle {
     attractor: Attractor,
+    goodie: Goodie,
+    kind: Item,
     pub sprite: SpriteSheetBundle,
 }
 
 impl GoodieBundle {
-    pub fn new(asset_server: &Res<AssetServer>, pos: Vec3) -> Self {
+    pub fn new(asset_server: &Res<AssetServer>, pos: Vec3, kind: ItemKind) -> Self {
         Self {
-            attractor: Attractor,
-            sprite: GoodieBundle::spritesheet(asset_server, pos),
+            attractor: kind.attractor(),
+            goodie: Goodie,
+            kind: Item(kind),
+            sprite: GoodieBundle::spritesheet(asset_server, pos, kind),
         }
     }
 
-    pub fn spritesheet(asset_server: &Res<AssetServer>, pos: Vec3) -> SpriteSheetBundle {
+    /// The atlas every goodie's sprite comes from
+    pub fn texture_atlas(asset_server: &AssetServer) -> Handle<TextureAtlas> {
         let texture_atlas = TextureAtlas::from_grid(
             asset_server.load("garbage.png"), 
             Vec2::new(32.0, 32.0), 
@@ -171,10 +444,14 @@ impl GoodieBundle {
             1,
             None, None
         );
-        let texture_atlas = asset_server.add(texture_atlas);
+        asset_server.add(texture_atlas)
+    }
+
+    pub fn spritesheet(asset_server: &Res<AssetServer>, pos: Vec3, kind: ItemKind) -> SpriteSheetBundle {
+        let texture_atlas = GoodieBundle::texture_atlas(asset_server);
         SpriteSheetBundle {
             sprite: TextureAtlasSprite{
-                index: 2,
+                index: kind.spec().sprite_index,
                 ..default()
             },
             texture_atlas,
@@ -202,24 +479,258 @@ pub struct ExitBundle {
     sprite_bundle: SpriteSheetBundle,
 }
 
+/// Wanders the level and ends the run if it catches the raccoon
+#[derive(Component, Clone, Debug)]
+pub struct Baddie {
+    /// Compared against the raccoon's level when they fight
+    pub level: u32,
+    /// Pull toward the current target each frame, when the level sets one rather than leaving it to [`Tunables`]
+    pub speed: Option<f32>,
+    /// How close the raccoon has to be before it's spotted, in pixels, when the level sets it
+    pub sight: Option<f32>,
+}
+
+impl Default for Baddie {
+    fn default() -> Self {
+        Baddie {
+            level: 1,
+            speed: None,
+            sight: None,
+        }
+    }
+}
+
+impl Baddie {
+    /// The level's speed for this baddie, or the tuned one
+    pub fn speed(&self, tunables: &Tunables) -> f32 {
+        self.speed.unwrap_or(tunables.baddie_speed)
+    }
+
+    /// The level's sight for this baddie, or the tuned one
+    pub fn sight(&self, tunables: &Tunables) -> f32 {
+        self.sight.unwrap_or(tunables.baddie_sight)
+    }
+}
+
+impl From<&EntityInstance> for Baddie {
+    fn from(entity_instance: &EntityInstance) -> Baddie {
+        let default = Baddie::default();
+        Baddie {
+            level: entity_instance.get_int_field("Level").map_or(default.level, |level| (*level).max(1) as u32),
+            speed: entity_instance.get_float_field("Speed").ok().copied(),
+            sight: entity_instance.get_float_field("Sight").ok().copied(),
+        }
+    }
+}
+
+/// Points, relative to the level, that a baddie walks through and back again
+#[derive(Component, Clone, Debug, Default)]
+pub struct Patrol {
+    pub points: Vec<Vec2>,
+    pub index: usize,
+    pub forward: bool,
+}
+
+impl Patrol {
+    pub fn from_instance(entity_instance: &EntityInstance, layer_instance: &LayerInstance) -> Patrol {
+        let level_height = layer_instance.c_hei * layer_instance.grid_size;
+        let mut points = vec![ldtk_pixel_coords_to_translation_pivoted(
+            entity_instance.px,
+            level_height,
+            IVec2::new(entity_instance.width, entity_instance.height),
+            entity_instance.pivot,
+        )];
+        if let Ok(ldtk_points) = entity_instance.iter_points_field("Patrol") {
+            for ldtk_point in ldtk_points {
+                // aim for the middle of the cell
+                let pixel_coords = (ldtk_point.as_vec2() + Vec2::splat(0.5)) * layer_instance.grid_size as f32;
+                points.push(ldtk_pixel_coords_to_translation(pixel_coords.as_ivec2(), level_height));
+            }
+        }
+        Patrol {
+            points,
+            index: 0,
+            forward: true,
+        }
+    }
+
+    /// Moves on to the next point, turning around at either end
+    pub fn advance(&mut self) {
+        if self.points.len() < 2 {
+            return;
+        }
+        if self.forward && self.index + 1 == self.points.len() {
+            self.forward = false;
+        } else if !self.forward && self.index == 0 {
+            self.forward = true;
+        }
+        if self.forward {
+            self.index += 1;
+        } else {
+            self.index -= 1;
+        }
+    }
+}
+
+impl LdtkEntity for Patrol {
+    fn bundle_entity(
+        entity_instance: &EntityInstance,
+        layer_instance: &LayerInstance,
+        _: Option<&Handle<Image>>,
+        _: Option<&TilesetDefinition>,
+        _: &AssetServer,
+        _: &mut Assets<TextureAtlas>,
+    ) -> Patrol {
+        Patrol::from_instance(entity_instance, layer_instance)
+    }
+}
+
+#[derive(Clone, Default, Bundle, LdtkEntity)]
+pub struct BaddieBundle {
+    #[from_entity_instance]
+    baddie: Baddie,
+    #[ldtk_entity]
+    patrol: Patrol,
+    #[sprite_sheet_bundle]
+    pub sprite_bundle: SpriteSheetBundle,
+    #[from_entity_instance]
+    collider: ColliderBundle,
+    animation_timer: AnimationTimer,
+}
+
 #[derive(Component, Default)]
 pub struct Placer;
 
 
-#[derive(Component, Default)]
+/// How many of each goodie the placer has left
+#[derive(Component, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Inventory {
-    pub count: u32,
+    counts: HashMap<ItemKind, u32>,
+}
+
+impl Inventory {
+    pub fn new(counts: &[(ItemKind, u32)]) -> Self {
+        Inventory {
+            counts: counts.iter().copied().collect(),
+        }
+    }
+
+    pub fn count(&self, kind: ItemKind) -> u32 {
+        self.counts.get(&kind).copied().unwrap_or(0)
+    }
+
+    /// Goodies of every kind put together
+    pub fn total(&self) -> u32 {
+        self.counts.values().sum()
+    }
+
+    pub fn add(&mut self, kind: ItemKind, count: u32) {
+        *self.counts.entry(kind).or_default() += count;
+    }
+
+    pub fn set(&mut self, kind: ItemKind, count: u32) {
+        self.counts.insert(kind, count);
+    }
+
+    /// Moves everything out of `other` into this inventory
+    pub fn absorb(&mut self, other: &mut Inventory) {
+        for (kind, count) in other.counts.drain() {
+            self.add(kind, count);
+        }
+    }
+
+    /// Removes one of `kind`, if there's any left
+    pub fn take(&mut self, kind: ItemKind) -> bool {
+        match self.counts.get_mut(&kind) {
+            Some(count) if *count > 0 => {
+                *count -= 1;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+/// The placer's inventory and the raccoon's progress as they were when the current level's planning began
+///
+/// Every raccoon starts planning with these stats, so they also carry the raccoon's progress into the next level.
+#[derive(Resource, Clone, Debug, Default)]
+pub struct LevelSnapshot {
+    pub inventory: Inventory,
+    pub stats: HeroStats,
+}
+
+/// The raccoon's stats as it finished the level, kept after it leaves for [`LevelSnapshot`] to take on
+#[derive(Resource, Clone, Debug, Default)]
+pub struct FinishedStats(pub HeroStats);
+
+/// A change the player made to the level while planning
+#[derive(Copy, Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub enum PlacementAction {
+    Place { pos: Vec3, kind: ItemKind },
+    PickUp { pos: Vec3, kind: ItemKind },
+}
+
+/// Undo and redo stacks for the current planning phase
+#[derive(Resource, Clone, Debug, Default)]
+pub struct PlacementHistory {
+    done: Vec<PlacementAction>,
+    undone: Vec<PlacementAction>,
 }
 
+impl PlacementHistory {
+    /// Records a fresh action, which forgets anything that was undone
+    pub fn push(&mut self, action: PlacementAction) {
+        self.done.push(action);
+        self.undone.clear();
+    }
+
+    /// The most recent action, now moved onto the redo stack
+    pub fn undo(&mut self) -> Option<PlacementAction> {
+        let action = self.done.pop()?;
+        self.undone.push(action);
+        Some(action)
+    }
+
+    /// The most recently undone action, now moved back onto the undo stack
+    pub fn redo(&mut self) -> Option<PlacementAction> {
+        let action = self.undone.pop()?;
+        self.done.push(action);
+        Some(action)
+    }
+
+    pub fn clear(&mut self) {
+        self.done.clear();
+        self.undone.clear();
+    }
+}
 
 #[derive(Bundle, Default)]
 pub struct PlacerBundle {
     pub placer: Placer,
     pub sprite_sheet_bundle: SpriteSheetBundle,
     pub inventory: Inventory,
+    pub selected: Selected,
 }
 
 
+/// The lit region showing what the raccoon can see
+#[derive(Component, Default)]
+pub struct SightRegion;
+
+/// What the sight overlay shows on top of the lit region
+#[derive(Resource, Clone, Debug)]
+pub struct SightOverlay {
+    /// Mark every attractor the raccoon can currently see
+    pub show_visible: bool,
+}
+
+impl Default for SightOverlay {
+    fn default() -> Self {
+        SightOverlay { show_visible: true }
+    }
+}
+
 #[derive(Resource, Default)]
 pub struct WorldMouse {
     pub pos: Option<Vec3>
@@ -0,0 +1,61 @@
+//! Gameplay milestones, sent as Bevy events
+//!
+//! The core systems only report what happened; anything that wants to react,
+//! from the state machine to scoring, sound or achievements, reads the events
+//! with an [`EventReader`] instead of reaching into those systems.
+
+use bevy::prelude::*;
+
+use crate::components::{FightResult, LoseCause};
+use crate::items::ItemKind;
+
+/// A goodie was put down while planning, by clicking or redoing
+#[derive(Event, Copy, Clone, PartialEq, Debug)]
+pub struct GoodiePlaced {
+    pub kind: ItemKind,
+    pub pos: Vec2,
+}
+
+/// A goodie was taken back into the placer, by right-clicking or undoing
+#[derive(Event, Copy, Clone, PartialEq, Debug)]
+pub struct GoodieRemoved {
+    pub kind: ItemKind,
+    pub pos: Vec2,
+}
+
+/// The raccoon ate an attractor
+#[derive(Event, Copy, Clone, PartialEq, Debug)]
+pub struct AttractorEaten {
+    pub at: Vec2,
+    /// Whether it was a goodie the player placed rather than the level's own garbage
+    pub placed: bool,
+}
+
+/// The raccoon has nothing in view or in mind to go for, and will stand there getting hungrier
+///
+/// Sent once each time it runs out of ideas.
+#[derive(Event, Copy, Clone, PartialEq, Debug)]
+pub struct HeroStalled {
+    pub at: Vec2,
+}
+
+/// The raccoon got into a scrap with a baddie
+#[derive(Event, Copy, Clone, PartialEq, Debug)]
+pub struct BaddieFought {
+    pub baddie_level: u32,
+    pub result: FightResult,
+}
+
+/// The raccoon reached the level's goal
+#[derive(Event, Copy, Clone, PartialEq, Debug)]
+pub struct LevelCompleted;
+
+/// The raccoon walked out through the victory room's exit
+#[derive(Event, Copy, Clone, PartialEq, Debug)]
+pub struct ExitReached;
+
+/// The run is lost
+#[derive(Event, Copy, Clone, PartialEq, Debug)]
+pub struct HeroDied {
+    pub cause: LoseCause,
+}
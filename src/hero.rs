@@ -0,0 +1,67 @@
+//! The raccoon and the baddies: how they move, eat, fight and win or lose a run
+//!
+//! Nothing here draws UI or reads input, so [`HeroPlugin`] runs just as well
+//! in a headless app such as the [`sim`](crate::sim).
+
+use bevy::prelude::*;
+use bevy_ecs_ldtk::prelude::*;
+use bevy_rapier2d::prelude::PhysicsSet;
+
+use crate::components::*;
+use crate::events::*;
+use crate::systems;
+use crate::tuning::Tunables;
+
+/// The raccoon's behaviour during [`GameState::Running`], and the baddies after it
+///
+/// Reads the current [`LevelRules`], which [`LevelPlugin`](crate::level::LevelPlugin) keeps up to date.
+pub struct HeroPlugin;
+
+impl Plugin for HeroPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<LoseCause>()
+            .init_resource::<Tunables>()
+            .init_resource::<RunClock>()
+            .init_resource::<RunTally>()
+            .add_event::<AttractorEaten>()
+            .add_event::<HeroStalled>()
+            .add_event::<BaddieFought>()
+            .add_event::<LevelCompleted>()
+            .add_event::<ExitReached>()
+            .add_event::<HeroDied>()
+            .register_ldtk_entity::<PlayerBundle>("Raccoon")
+            .register_ldtk_entity::<BaddieBundle>("Baddie")
+            .add_systems(OnEnter(GameState::Running), (systems::reset_run_clock, systems::reset_run_tally, systems::fill_hunger))
+            .add_systems(OnEnter(GameState::AdvanceLevel), despawn_player)
+            .add_systems(Update, (systems::tint_baddies, systems::time_animations))
+            // a fixed step keeps runs the same at any frame rate
+            .add_systems(FixedUpdate, (
+                systems::damp_movement,
+                systems::face_movement,
+                (
+                    systems::update_player,
+                    systems::update_baddies,
+                    systems::check_win,
+                    systems::fight_baddies,
+                    systems::check_time_limit,
+                ).chain().run_if(in_state(GameState::Running)),
+                systems::walk_to_exit.run_if(in_state(GameState::GameWin)),
+            ).chain().before(PhysicsSet::SyncBackend))
+            // outcomes are read every frame, so none are missed when no fixed step runs
+            .add_systems(PostUpdate, (
+                systems::animate_death.run_if(in_state(GameState::GameLose)),
+                systems::animate_walk,
+                systems::tally_garbage,
+                systems::finish_run,
+            ));
+    }
+}
+
+fn despawn_player(
+    player: Query<Entity, With<Player>>,
+    mut commands: Commands,
+) {
+    for player in player.iter() {
+        commands.entity(player).despawn();
+    }
+}
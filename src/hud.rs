@@ -0,0 +1,297 @@
+//! On-screen feedback: status text, the raccoon's stats, the results screen and the sight overlay
+
+use bevy::{
+    prelude::*,
+    render::mesh::{Indices, PrimitiveTopology},
+    sprite::MaterialMesh2dBundle,
+    text::BreakLineOn,
+};
+use bevy_ecs_ldtk::prelude::*;
+
+use crate::components::*;
+use crate::level::{loaded_project, record_results};
+use crate::replay::Playback;
+use crate::save::SaveData;
+use crate::score::LevelScore;
+use crate::systems;
+
+/// Status and stats text, the results screen between levels, and what the raccoon can see
+pub struct HudPlugin;
+
+impl Plugin for HudPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SightOverlay>()
+            .add_systems(Startup, (spawn_hud, spawn_sight_region))
+            .add_systems(OnEnter(GameState::MainMenu), hide_sight_region)
+            .add_systems(OnEnter(GameState::AdvanceLevel), hide_sight_region)
+            .add_systems(OnEnter(GameState::GameLose), hide_sight_region)
+            .add_systems(OnEnter(GameState::Results), show_results.after(record_results))
+            .add_systems(OnExit(GameState::Results), hide_results)
+            .add_systems(Update, (
+                toggle_sight_overlay.run_if(in_state(GameState::Planning).or_else(in_state(GameState::Running))),
+                update_hero_stats_text,
+                update_hud.run_if(state_changed::<GameState>()),
+            ))
+            .add_systems(PostUpdate, (
+                systems::update_sight_region,
+                systems::draw_sight_lines,
+            ).run_if(in_state(GameState::Planning).or_else(in_state(GameState::Running))))
+            .add_systems(PostUpdate, systems::draw_memories.run_if(in_state(GameState::Running)));
+    }
+}
+
+fn spawn_hud(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+) {
+    Hud::spawn(&mut commands, asset_server);
+}
+
+fn spawn_sight_region(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    // reshaped every frame by update_sight_region, between the walls and the sprites
+    let mut sight_mesh = Mesh::new(PrimitiveTopology::TriangleList);
+    sight_mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, vec![[0.0, 0.0, 0.0]; 3]);
+    sight_mesh.set_indices(Some(Indices::U32(vec![0, 1, 2])));
+    commands.spawn(MaterialMesh2dBundle {
+        mesh: meshes.add(sight_mesh).into(),
+        material: materials.add(ColorMaterial::from(Color::rgba(1.0, 0.95, 0.6, 0.12))),
+        transform: Transform::from_xyz(0.0, 0.0, 1.5),
+        visibility: Visibility::Hidden,
+        ..default()
+    }).insert(SightRegion);
+}
+
+#[derive(Component)]
+struct Hud;
+
+impl Hud {
+    fn spawn(
+        commands: &mut Commands,
+        asset_server: Res<AssetServer>,
+    ) {
+        commands.spawn(TextBundle {
+            text: Text {
+                sections: vec![TextSection {
+                    value: "Hello, World!".to_string(),
+                    style: TextStyle {
+                        font_size: 40.0,
+                        color: Color::WHITE,
+                        font: asset_server.load("fonts/PixelifySans-Bold.ttf"),
+                    },
+                }],
+                alignment: TextAlignment::Center,
+                linebreak_behavior: BreakLineOn::WordBoundary,
+            },
+            ..Default::default()
+        }).insert(Hud);
+
+        commands.spawn(TextBundle {
+            text: Text::from_section("", TextStyle {
+                font_size: 20.0,
+                color: Color::WHITE,
+                font: asset_server.load("fonts/PixelifySans-Bold.ttf"),
+            }),
+            style: Style {
+                position_type: PositionType::Absolute,
+                left: Val::Px(10.0),
+                bottom: Val::Px(10.0),
+                ..default()
+            },
+            ..Default::default()
+        }).insert(HeroStatsText);
+    }
+}
+
+#[derive(Component)]
+struct HeroStatsText;
+
+fn update_hero_stats_text(
+    mut query: Query<&mut Text, With<HeroStatsText>>,
+    heroes: Query<&HeroStats, With<Player>>,
+    rules: Res<LevelRules>,
+    clock: Res<RunClock>,
+    state: Res<State<GameState>>,
+) {
+    for mut text in query.iter_mut() {
+        let Some(stats) = heroes.iter().next() else {
+            text.sections[0].value.clear();
+            continue;
+        };
+        text.sections[0].value = format!(
+            "Level {}  HP {}/{}  XP {}/{}  Hunger {:.0}%  Loot tier {}",
+            stats.level, stats.hp, stats.max_hp, stats.xp, stats.xp_to_next(), stats.hunger * 100.0, stats.loot_tier(),
+        );
+        if let (GameState::Running, Some(time_limit)) = (state.get(), rules.time_limit) {
+            text.sections[0].value += &format!("  Time {:.0}s", (time_limit - clock.0).max(0.0));
+        }
+    }
+}
+
+fn update_hud(
+    mut query: Query<&mut Text, With<Hud>>,
+    state: Res<State<GameState>>,
+    lose_cause: Res<LoseCause>,
+    rules: Res<LevelRules>,
+    playback: Res<Playback>,
+) {
+    for mut text in query.iter_mut() {
+        match state.get() {
+            GameState::MainMenu | GameState::LevelSelect | GameState::Settings | GameState::Credits | GameState::Instructions => {
+                text.sections[0].value.clear();
+            }
+            GameState::Focusing => {
+                text.sections[0].value = "Focusing".to_string();
+            }
+            GameState::Planning => {
+                text.sections[0].value = "Planning. Click to place, right-click to pick up, 1-3 or scroll to pick a goodie. Ctrl+Z/Ctrl+Y to undo/redo. V to mark what the raccoon sees. WASD to move camera. Space to Execute.".to_string();
+                if let Some(par) = rules.par {
+                    text.sections[0].value += &format!(" Par: {} goodies.", par);
+                }
+                if let Some(time_limit) = rules.time_limit {
+                    text.sections[0].value += &format!(" Time limit: {:.0}s.", time_limit);
+                }
+            }
+            GameState::Running => {
+                text.sections[0].value = "Running".to_string();
+            }
+            GameState::AdvanceLevel => {
+                text.sections[0].value = "Level Complete!".to_string();
+            }
+            GameState::Results if playback.is_playing() => {
+                text.sections[0].value = "Replay finished. R to return to your own attempt, L to watch again.".to_string();
+            }
+            GameState::Results => {
+                text.sections[0].value = "Level Complete! Space to continue, R to replay for a better score.".to_string();
+            }
+            GameState::GameWin => {
+                text.sections[0].value = "You Win!".to_string();
+            }
+            GameState::WinDance => {
+                text.sections[0].value = "Close the window to exit. Press Shift+R to play again or Esc for the menu.".to_string();
+            }
+            GameState::GameLose => match *lose_cause {
+                LoseCause::Starved => {
+                    text.sections[0].value = "The raccoon starved to death! Press R to retry the level or Shift+R to start over.".to_string();
+                }
+                LoseCause::Caught => {
+                    text.sections[0].value = "The raccoon got caught! Press R to retry the level or Shift+R to start over.".to_string();
+                }
+                LoseCause::TimeUp => {
+                    text.sections[0].value = "The raccoon ran out of time! Press R to retry the level or Shift+R to start over.".to_string();
+                }
+            },
+        
+        }
+    }
+}
+
+#[derive(Component)]
+struct ResultsScreen;
+
+fn show_results(
+    score: Res<LevelScore>,
+    save: Res<SaveData>,
+    level: Res<LevelSelection>,
+    projects: Query<&Handle<LdtkProject>>,
+    project_assets: Res<Assets<LdtkProject>>,
+    asset_server: Res<AssetServer>,
+    mut commands: Commands,
+) {
+    let level_number = match *level {
+        LevelSelection::Indices(indices) => indices.level + 1,
+        _ => panic!("level selection should be indices"),
+    };
+    let font = asset_server.load("fonts/PixelifySans-Bold.ttf");
+    let style = |font_size: f32, color: Color| TextStyle {
+        font_size,
+        color,
+        font: font.clone(),
+    };
+    let par = match score.par {
+        Some(par) => format!(" (par {})", par),
+        None => String::new(),
+    };
+    let lines = [
+        format!("Level {} cleared\n\n", level_number),
+        format!("Goodies used: {} of {}{}\n", score.used, score.budget, par),
+        format!("Time: {:.1}s\n", score.time),
+        format!("Garbage eaten: {}\n\n", score.garbage),
+        format!("Score: {}\n", score.points),
+    ];
+    let best = loaded_project(&projects, &project_assets)
+        .and_then(|project| project.find_raw_level_by_level_selection(&level))
+        .and_then(|cleared| save.best.get(&cleared.identifier));
+    let mut sections: Vec<TextSection> = lines
+        .into_iter()
+        .map(|line| TextSection::new(line, style(24.0, Color::WHITE)))
+        .collect();
+    for star in 1..=3 {
+        let color = if star <= score.stars { Color::GOLD } else { Color::DARK_GRAY };
+        sections.push(TextSection::new("* ", style(48.0, color)));
+    }
+    if let Some(best) = best {
+        sections.push(TextSection::new(format!("\nBest: {} ({} stars)", best.points, best.stars), style(20.0, Color::GRAY)));
+    }
+
+    commands.spawn(NodeBundle {
+        style: Style {
+            position_type: PositionType::Absolute,
+            width: Val::Percent(100.0),
+            height: Val::Percent(100.0),
+            align_items: AlignItems::Center,
+            justify_content: JustifyContent::Center,
+            ..default()
+        },
+        ..default()
+    }).insert(ResultsScreen).with_children(|parent| {
+        parent.spawn(NodeBundle {
+            style: Style {
+                padding: UiRect::all(Val::Px(24.0)),
+                ..default()
+            },
+            background_color: Color::rgba(0.0, 0.0, 0.0, 0.8).into(),
+            ..default()
+        }).with_children(|parent| {
+            parent.spawn(TextBundle::from_sections(sections).with_text_alignment(TextAlignment::Center));
+        });
+    });
+}
+
+fn hide_results(
+    screen: Query<Entity, With<ResultsScreen>>,
+    mut commands: Commands,
+) {
+    for screen in screen.iter() {
+        commands.entity(screen).despawn_recursive();
+    }
+}
+
+/// V shows or hides the marks on attractors the raccoon can see
+fn toggle_sight_overlay(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut overlay: ResMut<SightOverlay>,
+    mut save: ResMut<SaveData>,
+) {
+    if keyboard_input.just_pressed(KeyCode::V) {
+        set_sight_marks(!overlay.show_visible, &mut overlay, &mut save);
+    }
+}
+
+pub fn set_sight_marks(show: bool, overlay: &mut SightOverlay, save: &mut SaveData) {
+    overlay.show_visible = show;
+    save.settings.show_visible = show;
+    save.store();
+}
+
+/// The sight region only makes sense while there's a plan to make or a run to watch
+fn hide_sight_region(
+    mut region: Query<&mut Visibility, With<SightRegion>>,
+) {
+    for mut visibility in region.iter_mut() {
+        *visibility = Visibility::Hidden;
+    }
+}
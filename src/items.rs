@@ -0,0 +1,105 @@
+use bevy::math::Vec2;
+use serde::{Deserialize, Serialize};
+
+use crate::components::Attractor;
+
+/// The goodies the placer can put down, all drawn from `garbage.png`
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Default, Serialize, Deserialize)]
+pub enum ItemKind {
+    Scraps,
+    #[default]
+    HotDog,
+    Fish,
+}
+
+/// How strongly an item kind draws the raccoon in, and when it's available
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct ItemSpec {
+    pub name: &'static str,
+    /// Pull toward the item each frame once it's the raccoon's target
+    pub pull: f32,
+    /// Beyond this distance the raccoon ignores the item, in pixels
+    pub radius: f32,
+    /// Weight against distance when the raccoon picks between items it can see
+    pub priority: f32,
+    pub sprite_index: usize,
+    /// The hero's loot tier needed before it can be placed
+    pub tier: u32,
+}
+
+impl ItemKind {
+    pub const ALL: [ItemKind; 3] = [ItemKind::Scraps, ItemKind::HotDog, ItemKind::Fish];
+
+    pub fn spec(self) -> ItemSpec {
+        match self {
+            ItemKind::Scraps => ItemSpec {
+                name: "Scraps",
+                pull: 6.0,
+                radius: 80.0,
+                priority: 0.5,
+                sprite_index: 0,
+                tier: 1,
+            },
+            ItemKind::HotDog => ItemSpec {
+                name: "Hot Dog",
+                pull: 10.0,
+                radius: f32::INFINITY,
+                priority: 1.0,
+                sprite_index: 2,
+                tier: 1,
+            },
+            ItemKind::Fish => ItemSpec {
+                name: "Fish",
+                pull: 14.0,
+                radius: f32::INFINITY,
+                priority: 3.0,
+                sprite_index: 1,
+                tier: 2,
+            },
+        }
+    }
+
+    /// The strongest item the given loot tier has unlocked
+    pub fn best_unlocked(tier: u32) -> ItemKind {
+        ItemKind::ALL
+            .into_iter()
+            .filter(|kind| kind.spec().tier <= tier)
+            .max_by(|a, b| a.spec().priority.total_cmp(&b.spec().priority))
+            .unwrap_or_default()
+    }
+
+    /// Looks a kind up by name, ignoring case and spaces
+    pub fn from_name(name: &str) -> Option<ItemKind> {
+        let wanted: String = name.chars().filter(|c| !c.is_whitespace()).collect();
+        ItemKind::ALL.into_iter().find(|kind| {
+            let spec_name: String = kind.spec().name.chars().filter(|c| !c.is_whitespace()).collect();
+            spec_name.eq_ignore_ascii_case(&wanted)
+        })
+    }
+
+    pub fn attractor(self) -> Attractor {
+        let spec = self.spec();
+        Attractor {
+            pull: spec.pull,
+            radius: spec.radius,
+            priority: spec.priority,
+        }
+    }
+}
+
+/// A goodie put down before the run starts
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Placement {
+    /// World position, the same coordinates the placer uses in game
+    pub pos: Vec2,
+    pub kind: ItemKind,
+}
+
+impl From<Vec2> for Placement {
+    fn from(pos: Vec2) -> Self {
+        Placement {
+            pos,
+            kind: ItemKind::default(),
+        }
+    }
+}
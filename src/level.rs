@@ -0,0 +1,424 @@
+//! Which level is being played and how the game moves between levels
+//!
+//! The LDtk world, each level's [`LevelRules`], retrying and restarting,
+//! scoring a cleared level and recording it in the [`SaveData`].
+
+use bevy::prelude::*;
+use bevy_ecs_ldtk::prelude::*;
+
+use crate::components::*;
+use crate::physics::missing_physics;
+use crate::replay::replaying;
+use crate::save::SaveData;
+use crate::score::LevelScore;
+use crate::systems;
+use crate::tuning::Tunables;
+
+/// Loads `attic.ldtk` and runs the game's state machine from the first level to the last
+///
+/// Adds [`GameState`]; the other plugins expect it to be there.
+pub struct LevelPlugin;
+
+impl Plugin for LevelPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_state::<GameState>()
+            .insert_resource(LevelSelection::Indices(LevelIndices{level: 0, ..default()}))
+            .insert_resource(LdtkSettings {
+                level_spawn_behavior: LevelSpawnBehavior::UseWorldTranslation {
+                    load_level_neighbors: true,
+                },
+                set_clear_color: SetClearColor::FromLevelBackground,
+                ..Default::default()
+            })
+            .init_resource::<LevelRules>()
+            .init_resource::<LevelSnapshot>()
+            .init_resource::<FinishedStats>()
+            .init_resource::<LevelScore>()
+            .init_resource::<SaveData>()
+            .register_ldtk_int_cell::<WallBundle>(2)
+            .register_ldtk_entity::<GoalBundle>("Trash")
+            .register_ldtk_entity::<GarbageBundle>("Garbage")
+            .register_ldtk_entity::<ExitBundle>("Exit")
+            .add_systems(Startup, (load_save, spawn_world))
+            .add_systems(OnEnter(GameState::Planning), snapshot_level)
+            .add_systems(OnEnter(GameState::AdvanceLevel), (
+                score_level,
+                time_level_end::<Goal>,
+                keep_finished_stats.run_if(not(replaying)),
+            ))
+            .add_systems(OnEnter(GameState::GameLose), time_level_end::<Player>)
+            .add_systems(OnEnter(GameState::Results), record_results.run_if(not(replaying)))
+            .add_systems(Update, (
+                systems::spawn_wall_collision,
+                validate_physics,
+                load_level_rules,
+            ))
+            .add_systems(Update, (
+                start_game.run_if(in_state(GameState::Instructions)),
+                restart.run_if(not(in_menu)),
+                retry_level.run_if(can_retry),
+                advance_level.run_if(in_state(GameState::AdvanceLevel)),
+                leave_results.run_if(in_state(GameState::Results).and_then(not(replaying))),
+                animate_exit.run_if(in_state(GameState::GameWin)),
+                animate_win_dance.run_if(in_state(GameState::WinDance)),
+            ));
+    }
+}
+
+fn spawn_world(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+) {
+    let ldtk_handle = asset_server.load("attic.ldtk");
+    commands.spawn(LdtkWorldBundle {
+        ldtk_handle,
+        ..Default::default()
+    });
+}
+
+/// The LDtk project, once its asset has finished loading
+pub fn loaded_project<'a>(
+    projects: &Query<&Handle<LdtkProject>>,
+    project_assets: &'a Assets<LdtkProject>,
+) -> Option<&'a LdtkProject> {
+    projects.iter().next().and_then(|handle| project_assets.get(handle))
+}
+
+/// Whether one of the screens outside of a level is showing
+fn in_menu(state: Res<State<GameState>>) -> bool {
+    state.get().is_menu()
+}
+
+/// Whether R retries the level in the current state
+pub fn can_retry(state: Res<State<GameState>>) -> bool {
+    matches!(
+        state.get(),
+        GameState::Planning | GameState::Running | GameState::GameLose | GameState::Results
+    )
+}
+
+/// Reads the save before anything is shown, and puts its settings into effect
+fn load_save(mut commands: Commands) {
+    let save = SaveData::load();
+    commands.insert_resource(SightOverlay {
+        show_visible: save.settings.show_visible,
+    });
+    commands.insert_resource(save);
+}
+
+/// Sends the player back to the main menu when the project, as loaded or hot reloaded,
+/// has entities with no physics definition
+fn validate_physics(
+    mut events: EventReader<AssetEvent<LdtkProject>>,
+    project_assets: Res<Assets<LdtkProject>>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    for event in events.read() {
+        if let AssetEvent::LoadedWithDependencies { id } | AssetEvent::Modified { id } = event {
+            if let Some(project) = project_assets.get(*id) {
+                let missing = missing_physics(&project.json_data().defs);
+                if !missing.is_empty() {
+                    error!("no physics definition for LDtk entities: {}", missing.join(", "));
+                    next_state.set(GameState::MainMenu);
+                }
+            }
+        }
+    }
+}
+
+/// Reads the selected level's rules from the LDtk project and restocks the placer with its budget
+fn load_level_rules(
+    level: Res<LevelSelection>,
+    projects: Query<&Handle<LdtkProject>>,
+    project_assets: Res<Assets<LdtkProject>>,
+    mut rules: ResMut<LevelRules>,
+    mut snapshot: ResMut<LevelSnapshot>,
+    mut placer: Query<&mut Inventory, With<Placer>>,
+    mut pending: Local<bool>,
+) {
+    if level.is_changed() {
+        *pending = true;
+    }
+    if !*pending {
+        return;
+    }
+    // the project and placer only show up once the instructions are dismissed
+    let Some(project) = loaded_project(&projects, &project_assets) else {
+        return;
+    };
+    let Some(mut inventory) = placer.iter_mut().next() else {
+        return;
+    };
+    *rules = project
+        .find_raw_level_by_level_selection(&level)
+        .map(LevelRules::from_level)
+        .unwrap_or_default();
+    *inventory = rules.budget.clone();
+    inventory.absorb(&mut snapshot.stats.loot);
+    *pending = false;
+}
+
+/// Takes the snapshot [`retry_level`] goes back to, and starts the raccoon off with the stats it carries
+fn snapshot_level(
+    inventory: Query<&Inventory, With<Placer>>,
+    mut heroes: Query<&mut HeroStats, With<Player>>,
+    mut snapshot: ResMut<LevelSnapshot>,
+) {
+    if let Some(inventory) = inventory.iter().next() {
+        snapshot.inventory = inventory.clone();
+    }
+    for mut stats in heroes.iter_mut() {
+        *stats = snapshot.stats.clone();
+    }
+}
+
+/// Holds on to the winning raccoon's stats, since it is gone by the time the results are shown
+fn keep_finished_stats(heroes: Query<&HeroStats, With<Player>>, mut finished: ResMut<FinishedStats>) {
+    if let Some(stats) = heroes.iter().next() {
+        finished.0 = stats.clone();
+    }
+}
+
+/// Scores the run that just reached the goal, before anything gets reset
+fn score_level(
+    snapshot: Res<LevelSnapshot>,
+    inventory: Query<&Inventory, With<Placer>>,
+    clock: Res<RunClock>,
+    tally: Res<RunTally>,
+    rules: Res<LevelRules>,
+    mut score: ResMut<LevelScore>,
+) {
+    let start = &snapshot.inventory;
+    let left = inventory.iter().next().unwrap_or(start);
+    *score = LevelScore::from_placer(&rules.budget, start, left, clock.0, tally.garbage_eaten, rules.par);
+}
+
+/// Starts the goal or death animation, on whichever of them is `T`, with the tuned length
+fn time_level_end<T: Component>(
+    tunables: Res<Tunables>,
+    ending: Query<Entity, With<T>>,
+    mut commands: Commands,
+) {
+    for entity in ending.iter() {
+        commands.entity(entity).insert(LevelEndTimer::from_seconds(tunables.level_end_seconds));
+    }
+}
+
+fn advance_level(
+    time: Res<Time>,
+    mut next_state: ResMut<NextState<GameState>>,
+    mut goal: Query<(&mut LevelEndTimer, &mut TextureAtlasSprite), With<Goal>>,
+) {
+    let (mut level_end_timer, mut goal_anim) = goal.single_mut();
+    if level_end_timer.0.tick(time.delta()).just_finished() {
+        next_state.set(GameState::Results);
+    } else {
+        // advance the goal animation linearly through time
+        let remaining = level_end_timer.0.remaining_secs();
+        let since_start = level_end_timer.0.duration().as_secs_f32() - remaining;
+        let completion_fraction = since_start / level_end_timer.0.duration().as_secs_f32();
+
+        // animation is in frame 1-7
+        let frame = (completion_fraction * 6.0) as usize;
+        goal_anim.index = 1 + frame;
+    }
+}
+
+fn animate_exit(
+    time: Res<Time>,
+    mut exit: Query<(&mut AnimationTimer, &mut TextureAtlasSprite), With<Exit>>,
+) {
+    for (mut timer, mut sprite) in exit.iter_mut() {
+        if timer.0.tick(time.delta()).just_finished() {
+            sprite.index = (sprite.index + 1) % 4;
+        }
+    }
+}
+
+fn animate_win_dance(
+    time: Res<Time>,
+    mut exit: Query<(&mut AnimationTimer, &mut TextureAtlasSprite), With<Exit>>,
+) {
+    for (mut timer, mut sprite) in exit.iter_mut() {
+        if timer.0.tick(time.delta()).just_finished() {
+            sprite.index = 4 + ((sprite.index + 1) % 3);
+        }
+    }
+}
+
+/// Keeps the level's best score and unlocks the one after it
+pub fn record_results(
+    score: Res<LevelScore>,
+    level: Res<LevelSelection>,
+    projects: Query<&Handle<LdtkProject>>,
+    project_assets: Res<Assets<LdtkProject>>,
+    mut save: ResMut<SaveData>,
+) {
+    let Some(project) = loaded_project(&projects, &project_assets) else {
+        return;
+    };
+    let LevelSelection::Indices(indices) = *level else {
+        panic!("level selection should be indices");
+    };
+    let mut levels = project.iter_raw_levels().skip(indices.level);
+    if let Some(cleared) = levels.next() {
+        save.record(&cleared.identifier, &score);
+    }
+    if let Some(next) = levels.next() {
+        save.unlock(&next.identifier);
+    }
+    save.store();
+}
+
+/// Moves on to the next level once the player has seen their score
+fn leave_results(
+    keyboard_input: Res<Input<KeyCode>>,
+    finished: Res<FinishedStats>,
+    mut snapshot: ResMut<LevelSnapshot>,
+    mut next_state: ResMut<NextState<GameState>>,
+    level: ResMut<LevelSelection>,
+    projects: Query<&Handle<LdtkProject>>,
+    project_assets: Res<Assets<LdtkProject>>,
+) {
+    if !keyboard_input.just_pressed(KeyCode::Space) {
+        return;
+    }
+    // the next level's raccoon picks up where this one left off
+    snapshot.stats = finished.0.clone();
+    let indices = match level.into_inner() {
+        LevelSelection::Indices(indices) => indices,
+        _ => panic!("level selection should be indices"),
+    };
+    let level_count = loaded_project(&projects, &project_assets)
+        .map_or(0, |project| project.iter_raw_levels().count());
+    if indices.level + 1 < level_count {
+        indices.level += 1;
+        next_state.set(GameState::Focusing);
+    } else {
+        // no victory room to walk to after the last level
+        next_state.set(GameState::WinDance);
+    }
+}
+
+/// Starts a new game once the instructions have been read
+pub fn start_game(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut snapshot: ResMut<LevelSnapshot>,
+    mut next_state: ResMut<NextState<GameState>>,
+    mut level: ResMut<LevelSelection>,
+    projects: Query<&Handle<LdtkProject>>,
+    project_assets: Res<Assets<LdtkProject>>,
+    levels: Query<(Entity, &LevelIid)>,
+    mut commands: Commands,
+) {
+    if keyboard_input.just_pressed(KeyCode::Space) {
+        begin_at(0, &mut level, &mut snapshot, &mut next_state, &projects, &project_assets, &levels, &mut commands);
+    }
+}
+
+/// Starts a fresh raccoon on the level at `index`
+///
+/// Selecting a level always restocks the placer from that level's rules, even if it's already selected.
+pub fn begin_at(
+    index: usize,
+    level: &mut LevelSelection,
+    snapshot: &mut LevelSnapshot,
+    next_state: &mut NextState<GameState>,
+    projects: &Query<&Handle<LdtkProject>>,
+    project_assets: &Assets<LdtkProject>,
+    levels: &Query<(Entity, &LevelIid)>,
+    commands: &mut Commands,
+) {
+    let selection = LevelSelection::Indices(LevelIndices { level: index, ..default() });
+    // selecting the level we're already on wouldn't spawn it again
+    if *level == selection {
+        respawn_selected_level(level, projects, project_assets, levels, commands);
+    }
+    *level = selection;
+    snapshot.stats = HeroStats::default();
+    next_state.set(GameState::Focusing);
+}
+
+/// Asks the LDtk plugin to spawn the selected level afresh
+fn respawn_selected_level(
+    level: &LevelSelection,
+    projects: &Query<&Handle<LdtkProject>>,
+    project_assets: &Assets<LdtkProject>,
+    levels: &Query<(Entity, &LevelIid)>,
+    commands: &mut Commands,
+) {
+    let Some(selected) = loaded_project(projects, project_assets)
+        .and_then(|project| project.find_raw_level_by_level_selection(level))
+    else {
+        return;
+    };
+    for (entity, iid) in levels.iter() {
+        if *iid.get() == selected.iid {
+            commands.entity(entity).insert(Respawn);
+        }
+    }
+}
+
+/// Puts the current level back the way it was when its planning began
+pub fn retry_level(
+    keyboard_input: Res<Input<KeyCode>>,
+    snapshot: Res<LevelSnapshot>,
+    mut inventory: Query<&mut Inventory, With<Placer>>,
+    mut next_state: ResMut<NextState<GameState>>,
+    level: Res<LevelSelection>,
+    projects: Query<&Handle<LdtkProject>>,
+    project_assets: Res<Assets<LdtkProject>>,
+    levels: Query<(Entity, &LevelIid)>,
+    mut commands: Commands,
+) {
+    let shift = keyboard_input.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+    if keyboard_input.just_pressed(KeyCode::R) && !shift {
+        reset_level(&snapshot, &mut inventory, &mut next_state, &level, &projects, &project_assets, &levels, &mut commands);
+    }
+}
+
+/// Respawns the selected level with the placer and raccoon as `snapshot` has them
+pub fn reset_level(
+    snapshot: &LevelSnapshot,
+    inventory: &mut Query<&mut Inventory, With<Placer>>,
+    next_state: &mut NextState<GameState>,
+    level: &LevelSelection,
+    projects: &Query<&Handle<LdtkProject>>,
+    project_assets: &Assets<LdtkProject>,
+    levels: &Query<(Entity, &LevelIid)>,
+    commands: &mut Commands,
+) {
+    respawn_selected_level(level, projects, project_assets, levels, commands);
+    if let Some(mut inventory) = inventory.iter_mut().next() {
+        *inventory = snapshot.inventory.clone();
+    }
+    next_state.set(GameState::Focusing);
+}
+
+/// LDtk identifier of the selected level
+pub fn selected_identifier(
+    level: &LevelSelection,
+    projects: &Query<&Handle<LdtkProject>>,
+    project_assets: &Assets<LdtkProject>,
+) -> Option<String> {
+    loaded_project(projects, project_assets)?
+        .find_raw_level_by_level_selection(level)
+        .map(|level| level.identifier.clone())
+}
+
+/// Starts the whole game over from the first level
+pub fn restart(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut snapshot: ResMut<LevelSnapshot>,
+    mut next_state: ResMut<NextState<GameState>>,
+    mut level: ResMut<LevelSelection>,
+    projects: Query<&Handle<LdtkProject>>,
+    project_assets: Res<Assets<LdtkProject>>,
+    levels: Query<(Entity, &LevelIid)>,
+    mut commands: Commands,
+) {
+    let shift = keyboard_input.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+    if keyboard_input.just_pressed(KeyCode::R) && shift {
+        begin_at(0, &mut level, &mut snapshot, &mut next_state, &projects, &project_assets, &levels, &mut commands);
+    }
+}
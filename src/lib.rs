@@ -0,0 +1,27 @@
+// queries and system signatures are naturally long in bevy
+#![allow(clippy::type_complexity, clippy::too_many_arguments)]
+
+pub mod camera;
+pub mod components;
+pub mod events;
+pub mod hero;
+pub mod hud;
+pub mod items;
+pub mod level;
+pub mod menu;
+pub mod nav;
+pub mod physics;
+pub mod placement;
+pub mod replay;
+pub mod save;
+pub mod score;
+pub mod sim;
+pub mod storage;
+pub mod systems;
+pub mod tuning;
+
+/// Pixels per Rapier meter. Rapier takes this when it starts, so unlike the
+/// [`tuning`] numbers it can't change while the game runs.
+pub const PHYSICS_SCALE: f32 = 100.0;
+/// Length of one gameplay and physics step, in seconds, whatever the frame rate
+pub const TIMESTEP: f32 = 1.0 / 60.0;
@@ -0,0 +1,324 @@
+//! The screens outside of a level: title, level select, settings, credits and how to play
+
+use bevy::{ecs::system::EntityCommands, prelude::*};
+use bevy_ecs_ldtk::prelude::*;
+
+use crate::components::*;
+use crate::hud::set_sight_marks;
+use crate::level::{begin_at, loaded_project};
+use crate::save::SaveData;
+
+/// Menu screens driven by the menu [`GameState`]s, with Esc bringing back the title screen from anywhere
+pub struct MenuPlugin;
+
+impl Plugin for MenuPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(OnEnter(GameState::MainMenu), spawn_main_menu)
+            .add_systems(OnEnter(GameState::Settings), spawn_settings)
+            .add_systems(OnEnter(GameState::Credits), spawn_credits)
+            .add_systems(OnEnter(GameState::Instructions), spawn_instructions)
+            .add_systems(OnExit(GameState::MainMenu), despawn_menu)
+            .add_systems(OnExit(GameState::LevelSelect), despawn_menu)
+            .add_systems(OnExit(GameState::Settings), despawn_menu)
+            .add_systems(OnExit(GameState::Credits), despawn_menu)
+            .add_systems(OnExit(GameState::Instructions), despawn_menu)
+            .add_systems(Update, (
+                menu_buttons,
+                highlight_buttons,
+                open_menu,
+                build_level_select.run_if(in_state(GameState::LevelSelect)),
+                update_settings_labels.run_if(in_state(GameState::Settings)),
+            ));
+    }
+}
+
+/// The root of whichever menu screen is showing
+#[derive(Component)]
+struct MenuScreen;
+
+/// What clicking a menu button does
+#[derive(Component, Copy, Clone, PartialEq, Debug)]
+enum MenuButton {
+    Play,
+    /// Picks up from the furthest level unlocked
+    Continue,
+    Levels,
+    Settings,
+    Credits,
+    Back,
+    /// Starts a fresh game from the level at this index
+    Level(usize),
+    ToggleSightMarks,
+    EraseSave,
+}
+
+#[derive(Component)]
+struct SightMarksLabel;
+
+const BUTTON_COLOR: Color = Color::rgb(0.2, 0.2, 0.25);
+
+const BUTTON_HOVER_COLOR: Color = Color::rgb(0.3, 0.3, 0.4);
+
+const BUTTON_LOCKED_COLOR: Color = Color::rgb(0.1, 0.1, 0.1);
+
+/// Spawns a screen covering the level, with its contents in a centred column
+fn spawn_menu_screen(commands: &mut Commands, contents: impl FnOnce(&mut ChildBuilder)) {
+    commands.spawn(NodeBundle {
+        style: Style {
+            position_type: PositionType::Absolute,
+            width: Val::Percent(100.0),
+            height: Val::Percent(100.0),
+            flex_direction: FlexDirection::Column,
+            align_items: AlignItems::Center,
+            justify_content: JustifyContent::Center,
+            row_gap: Val::Px(12.0),
+            ..default()
+        },
+        background_color: Color::rgb(0.05, 0.05, 0.08).into(),
+        z_index: ZIndex::Global(10),
+        ..default()
+    }).insert(MenuScreen).with_children(contents);
+}
+
+fn menu_text(parent: &mut ChildBuilder, asset_server: &AssetServer, text: impl Into<String>, font_size: f32) {
+    parent.spawn(TextBundle::from_section(text, TextStyle {
+        font_size,
+        color: Color::WHITE,
+        font: asset_server.load("fonts/PixelifySans-Bold.ttf"),
+    }).with_text_alignment(TextAlignment::Center));
+}
+
+fn menu_button<'w, 's, 'a>(
+    parent: &'a mut ChildBuilder<'w, 's, '_>,
+    asset_server: &AssetServer,
+    label: impl Into<String>,
+    action: MenuButton,
+) -> EntityCommands<'w, 's, 'a> {
+    let mut button = parent.spawn(ButtonBundle {
+        style: Style {
+            width: Val::Px(320.0),
+            padding: UiRect::all(Val::Px(8.0)),
+            justify_content: JustifyContent::Center,
+            ..default()
+        },
+        background_color: BUTTON_COLOR.into(),
+        ..default()
+    });
+    button.insert(action).with_children(|parent| {
+        menu_text(parent, asset_server, label, 28.0);
+    });
+    button
+}
+
+fn spawn_main_menu(
+    save: Res<SaveData>,
+    asset_server: Res<AssetServer>,
+    mut commands: Commands,
+) {
+    spawn_menu_screen(&mut commands, |parent| {
+        menu_text(parent, &asset_server, "Raccoon Tycoon", 64.0);
+        if !save.unlocked.is_empty() {
+            menu_button(parent, &asset_server, "Continue", MenuButton::Continue);
+        }
+        menu_button(parent, &asset_server, "Play", MenuButton::Play);
+        menu_button(parent, &asset_server, "Levels", MenuButton::Levels);
+        menu_button(parent, &asset_server, "Settings", MenuButton::Settings);
+        menu_button(parent, &asset_server, "Credits", MenuButton::Credits);
+    });
+}
+
+/// Lays out a button for every level once the project has loaded, locked ones greyed out
+fn build_level_select(
+    save: Res<SaveData>,
+    projects: Query<&Handle<LdtkProject>>,
+    project_assets: Res<Assets<LdtkProject>>,
+    screen: Query<(), With<MenuScreen>>,
+    asset_server: Res<AssetServer>,
+    mut commands: Commands,
+) {
+    if !screen.is_empty() {
+        return;
+    }
+    let Some(project) = loaded_project(&projects, &project_assets) else {
+        return;
+    };
+    spawn_menu_screen(&mut commands, |parent| {
+        menu_text(parent, &asset_server, "Levels", 48.0);
+        parent.spawn(NodeBundle {
+            style: Style {
+                display: Display::Grid,
+                grid_template_columns: RepeatedGridTrack::px(4, 140.0),
+                row_gap: Val::Px(12.0),
+                column_gap: Val::Px(12.0),
+                ..default()
+            },
+            ..default()
+        }).with_children(|grid| {
+            // victory rooms aren't puzzles, they're reached by clearing the level before
+            for (index, level) in project.iter_raw_levels().enumerate().filter(|(_, level)| !is_victory_room(level)) {
+                let unlocked = save.is_unlocked(index, &level.identifier);
+                let stars = save.best.get(&level.identifier).map_or(0, |best| best.stars);
+                let label = if unlocked {
+                    format!("{}\n{}", index + 1, "*".repeat(stars as usize))
+                } else {
+                    format!("{}\nLocked", index + 1)
+                };
+                let mut button = grid.spawn(ButtonBundle {
+                    style: Style {
+                        height: Val::Px(100.0),
+                        justify_content: JustifyContent::Center,
+                        align_items: AlignItems::Center,
+                        ..default()
+                    },
+                    background_color: if unlocked { BUTTON_COLOR } else { BUTTON_LOCKED_COLOR }.into(),
+                    ..default()
+                });
+                if unlocked {
+                    button.insert(MenuButton::Level(index));
+                }
+                button.with_children(|parent| menu_text(parent, &asset_server, label, 28.0));
+            }
+        });
+        menu_button(parent, &asset_server, "Back", MenuButton::Back);
+    });
+}
+
+fn spawn_settings(
+    asset_server: Res<AssetServer>,
+    mut commands: Commands,
+) {
+    spawn_menu_screen(&mut commands, |parent| {
+        menu_text(parent, &asset_server, "Settings", 48.0);
+        menu_button(parent, &asset_server, "", MenuButton::ToggleSightMarks).insert(SightMarksLabel);
+        menu_button(parent, &asset_server, "Erase save data", MenuButton::EraseSave);
+        menu_button(parent, &asset_server, "Back", MenuButton::Back);
+    });
+}
+
+fn update_settings_labels(
+    overlay: Res<SightOverlay>,
+    toggles: Query<&Children, With<SightMarksLabel>>,
+    mut texts: Query<&mut Text>,
+) {
+    for children in toggles.iter() {
+        for child in children.iter() {
+            if let Ok(mut text) = texts.get_mut(*child) {
+                let state = if overlay.show_visible { "On" } else { "Off" };
+                text.sections[0].value = format!("Sight marks: {}", state);
+            }
+        }
+    }
+}
+
+fn spawn_credits(
+    asset_server: Res<AssetServer>,
+    mut commands: Commands,
+) {
+    spawn_menu_screen(&mut commands, |parent| {
+        menu_text(parent, &asset_server, "Credits", 48.0);
+        menu_text(
+            parent,
+            &asset_server,
+            "Made for Ludum Dare 55, theme \"Summoning\"\n\nBuilt with Bevy, bevy_ecs_ldtk and Rapier\nLevels made in LDtk\nPixelify Sans and Fira Sans fonts",
+            24.0,
+        );
+        menu_button(parent, &asset_server, "Back", MenuButton::Back);
+    });
+}
+
+fn spawn_instructions(
+    asset_server: Res<AssetServer>,
+    mut commands: Commands,
+) {
+    spawn_menu_screen(&mut commands, |parent| {
+        parent.spawn(ImageBundle {
+            image: asset_server.load("instructions.png").into(),
+            style: Style {
+                width: Val::Px(1200.0),
+                height: Val::Px(600.0),
+                ..default()
+            },
+            ..default()
+        });
+        menu_text(parent, &asset_server, "Press Space to start, Esc to go back", 24.0);
+    });
+}
+
+fn despawn_menu(
+    screens: Query<Entity, With<MenuScreen>>,
+    mut commands: Commands,
+) {
+    for screen in screens.iter() {
+        commands.entity(screen).despawn_recursive();
+    }
+}
+
+fn highlight_buttons(
+    mut buttons: Query<(&Interaction, &mut BackgroundColor), (Changed<Interaction>, With<MenuButton>)>,
+) {
+    for (interaction, mut color) in buttons.iter_mut() {
+        *color = match interaction {
+            Interaction::Hovered | Interaction::Pressed => BUTTON_HOVER_COLOR,
+            Interaction::None => BUTTON_COLOR,
+        }.into();
+    }
+}
+
+fn menu_buttons(
+    buttons: Query<(&Interaction, &MenuButton), Changed<Interaction>>,
+    mut next_state: ResMut<NextState<GameState>>,
+    mut level: ResMut<LevelSelection>,
+    mut snapshot: ResMut<LevelSnapshot>,
+    mut save: ResMut<SaveData>,
+    mut overlay: ResMut<SightOverlay>,
+    projects: Query<&Handle<LdtkProject>>,
+    project_assets: Res<Assets<LdtkProject>>,
+    levels: Query<(Entity, &LevelIid)>,
+    mut commands: Commands,
+) {
+    for (interaction, button) in buttons.iter() {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        match *button {
+            MenuButton::Play => next_state.set(GameState::Instructions),
+            MenuButton::Continue => {
+                let furthest = loaded_project(&projects, &project_assets).and_then(|project| {
+                    project
+                        .iter_raw_levels()
+                        .enumerate()
+                        .filter(|(index, level)| save.is_unlocked(*index, &level.identifier) && !is_victory_room(level))
+                        .map(|(index, _)| index)
+                        .last()
+                });
+                if let Some(index) = furthest {
+                    begin_at(index, &mut level, &mut snapshot, &mut next_state, &projects, &project_assets, &levels, &mut commands);
+                }
+            }
+            MenuButton::Levels => next_state.set(GameState::LevelSelect),
+            MenuButton::Settings => next_state.set(GameState::Settings),
+            MenuButton::Credits => next_state.set(GameState::Credits),
+            MenuButton::Back => next_state.set(GameState::MainMenu),
+            MenuButton::Level(index) => {
+                begin_at(index, &mut level, &mut snapshot, &mut next_state, &projects, &project_assets, &levels, &mut commands);
+            }
+            MenuButton::ToggleSightMarks => set_sight_marks(!overlay.show_visible, &mut overlay, &mut save),
+            MenuButton::EraseSave => {
+                *save = SaveData::default();
+                overlay.show_visible = save.settings.show_visible;
+                save.store();
+            }
+        }
+    }
+}
+
+/// Esc goes back to the title screen from anywhere else
+fn open_menu(
+    keyboard_input: Res<Input<KeyCode>>,
+    state: Res<State<GameState>>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if keyboard_input.just_pressed(KeyCode::Escape) && *state.get() != GameState::MainMenu {
+        next_state.set(GameState::MainMenu);
+    }
+}
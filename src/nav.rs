@@ -0,0 +1,291 @@
+//! Grid navigation over a level's walls
+//!
+//! A [`NavGrid`] sits on each level entity, built from the same wall
+//! [`GridCoords`] that become the level's colliders. The raccoon keeps a
+//! [`Memory`] of the attractors it has seen lately; once nothing is in view it
+//! heads for the freshest one, walking around walls over the grid in levels
+//! with pathfinding turned on.
+
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+
+use bevy::prelude::*;
+use bevy_ecs_ldtk::prelude::*;
+use bevy_rapier2d::prelude::*;
+
+use crate::systems::line_of_sight;
+
+/// Step costs, scaled so diagonals stay integers
+const STRAIGHT_COST: u32 = 10;
+const DIAGONAL_COST: u32 = 14;
+
+/// Which cells of a level can be walked through, in level-local coordinates
+#[derive(Component, Clone, Debug)]
+pub struct NavGrid {
+    width: i32,
+    height: i32,
+    grid_size: f32,
+    blocked: Vec<bool>,
+}
+
+impl NavGrid {
+    pub fn new(walls: &HashSet<GridCoords>, width: i32, height: i32, grid_size: i32) -> Self {
+        let mut blocked = vec![false; (width * height).max(0) as usize];
+        for wall in walls {
+            if (0..width).contains(&wall.x) && (0..height).contains(&wall.y) {
+                blocked[(wall.y * width + wall.x) as usize] = true;
+            }
+        }
+        NavGrid {
+            width,
+            height,
+            grid_size: grid_size as f32,
+            blocked,
+        }
+    }
+
+    /// Whether a level-local position falls inside the level at all
+    pub fn contains(&self, local: Vec2) -> bool {
+        let cell = self.cell_at(local);
+        (0..self.width).contains(&cell.x) && (0..self.height).contains(&cell.y)
+    }
+
+    pub fn is_open(&self, cell: IVec2) -> bool {
+        (0..self.width).contains(&cell.x)
+            && (0..self.height).contains(&cell.y)
+            && !self.blocked[(cell.y * self.width + cell.x) as usize]
+    }
+
+    pub fn cell_at(&self, local: Vec2) -> IVec2 {
+        (local / self.grid_size).floor().as_ivec2()
+    }
+
+    pub fn cell_center(&self, cell: IVec2) -> Vec2 {
+        (cell.as_vec2() + Vec2::splat(0.5)) * self.grid_size
+    }
+
+    /// A* from one level-local position to another, as the cell centres to walk through
+    ///
+    /// The last waypoint is `to` itself. Diagonal steps never cut a wall's corner.
+    pub fn find_path(&self, from: Vec2, to: Vec2) -> Option<Vec<Vec2>> {
+        let start = self.cell_at(from);
+        let goal = self.cell_at(to);
+        if !self.is_open(start) || !self.is_open(goal) {
+            return None;
+        }
+
+        let heuristic = |cell: IVec2| {
+            let d = (goal - cell).abs();
+            let (long, short) = (d.x.max(d.y) as u32, d.x.min(d.y) as u32);
+            STRAIGHT_COST * (long - short) + DIAGONAL_COST * short
+        };
+
+        let mut open = BinaryHeap::new();
+        let mut cost: HashMap<IVec2, u32> = HashMap::new();
+        let mut came_from: HashMap<IVec2, IVec2> = HashMap::new();
+        cost.insert(start, 0);
+        open.push(Reverse((heuristic(start), start.x, start.y)));
+
+        while let Some(Reverse((_, x, y))) = open.pop() {
+            let cell = IVec2::new(x, y);
+            if cell == goal {
+                let mut path = vec![to];
+                let mut step = cell;
+                while let Some(previous) = came_from.get(&step) {
+                    if *previous != start {
+                        path.push(self.cell_center(*previous));
+                    }
+                    step = *previous;
+                }
+                path.reverse();
+                return Some(path);
+            }
+
+            let here = cost[&cell];
+            for dx in -1..=1 {
+                for dy in -1..=1 {
+                    let step = IVec2::new(dx, dy);
+                    if step == IVec2::ZERO || !self.is_open(cell + step) {
+                        continue;
+                    }
+                    let diagonal = dx != 0 && dy != 0;
+                    if diagonal && !(self.is_open(cell + IVec2::new(dx, 0)) && self.is_open(cell + IVec2::new(0, dy))) {
+                        continue;
+                    }
+                    let next = cell + step;
+                    let next_cost = here + if diagonal { DIAGONAL_COST } else { STRAIGHT_COST };
+                    if cost.get(&next).is_none_or(|known| next_cost < *known) {
+                        cost.insert(next, next_cost);
+                        came_from.insert(next, cell);
+                        open.push(Reverse((next_cost + heuristic(next), next.x, next.y)));
+                    }
+                }
+            }
+        }
+        None
+    }
+}
+
+/// The level whose grid covers `pos`, with the level's world translation
+pub fn nav_grid_at<'a>(
+    grids: impl IntoIterator<Item = (&'a NavGrid, &'a GlobalTransform)>,
+    pos: Vec2,
+) -> Option<(&'a NavGrid, Vec2)> {
+    grids
+        .into_iter()
+        .map(|(grid, xform)| (grid, xform.translation().truncate()))
+        .find(|(grid, origin)| grid.contains(pos - *origin))
+}
+
+/// An attractor the raccoon saw, and how long ago
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Sighting {
+    /// Where it was, in world space
+    pub at: Vec2,
+    pub pull: f32,
+    /// Seconds since it was last in view
+    pub age: f32,
+}
+
+/// Attractors the raccoon has seen lately, kept for a while after they go out of sight
+#[derive(Component, Clone, Debug, Default)]
+pub struct Memory {
+    pub sightings: Vec<Sighting>,
+    /// Waypoints still to walk through to reach the freshest sighting, in world space
+    pub path: Vec<Vec2>,
+    /// The sighting `path` leads to
+    path_to: Option<Vec2>,
+}
+
+impl Memory {
+    /// Ages every sighting by `dt`, forgetting the ones more than `span` seconds old
+    pub fn tick(&mut self, dt: f32, span: f32) {
+        for sighting in self.sightings.iter_mut() {
+            sighting.age += dt;
+        }
+        self.sightings.retain(|sighting| sighting.age < span);
+    }
+
+    /// Notes that the attractor at `at` is in view right now
+    pub fn see(&mut self, at: Vec2, pull: f32) {
+        match self.sightings.iter_mut().find(|sighting| sighting.at.distance(at) < 0.5) {
+            Some(sighting) => {
+                sighting.pull = pull;
+                sighting.age = 0.0;
+            }
+            None => self.sightings.push(Sighting { at, pull, age: 0.0 }),
+        }
+    }
+
+    /// Drops the sighting of the attractor at `at`, which is gone now
+    pub fn forget_at(&mut self, at: Vec2) {
+        self.sightings.retain(|sighting| sighting.at.distance(at) >= 0.5);
+    }
+
+    /// The attractor the raccoon saw most recently
+    pub fn freshest(&self) -> Option<Sighting> {
+        self.sightings
+            .iter()
+            .copied()
+            .min_by(|a, b| a.age.total_cmp(&b.age))
+    }
+
+    /// Where to head next, and how hard, when nothing is in view
+    ///
+    /// Goes straight for the freshest sighting, or along a route over `nav` when it's given. A
+    /// sighting is forgotten once the raccoon gets within `reach` without finding it, or when there's no way there.
+    pub fn next_waypoint(
+        &mut self,
+        rapier: &RapierContext,
+        reach: f32,
+        from: Vec2,
+        nav: Option<(&NavGrid, Vec2)>,
+    ) -> Option<(Vec2, f32)> {
+        while let Some(target) = self.freshest() {
+            if target.at.distance(from) < reach {
+                // made it, and whatever was there is still out of sight
+                self.forget_at(target.at);
+                continue;
+            }
+            let Some((grid, origin)) = nav else {
+                return Some((target.at, target.pull));
+            };
+
+            if self.path_to != Some(target.at) || self.path.is_empty() {
+                self.path_to = Some(target.at);
+                match grid.find_path(from - origin, target.at - origin) {
+                    Some(path) => self.path = path.into_iter().map(|point| point + origin).collect(),
+                    None => {
+                        self.forget_at(target.at);
+                        continue;
+                    }
+                }
+            }
+
+            // cut corners wherever the way ahead is clear
+            while self.path.len() > 1 && line_of_sight(rapier, from, self.path[1]) {
+                self.path.remove(0);
+            }
+            while self.path.first().is_some_and(|waypoint| waypoint.distance(from) < grid.grid_size / 2.0) {
+                self.path.remove(0);
+            }
+            match self.path.first() {
+                Some(waypoint) => return Some((*waypoint, target.pull)),
+                None => self.forget_at(target.at),
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GRID_SIZE: i32 = 10;
+
+    fn grid(walls: &[(i32, i32)]) -> NavGrid {
+        let walls = walls.iter().map(|(x, y)| GridCoords::new(*x, *y)).collect();
+        NavGrid::new(&walls, 5, 5, GRID_SIZE)
+    }
+
+    fn center(x: i32, y: i32) -> Vec2 {
+        (Vec2::new(x as f32, y as f32) + Vec2::splat(0.5)) * GRID_SIZE as f32
+    }
+
+    #[test]
+    fn start_at_goal_walks_straight_to_it() {
+        let grid = grid(&[]);
+        let to = center(2, 2) + Vec2::new(1.0, -2.0);
+        assert_eq!(grid.find_path(center(2, 2), to), Some(vec![to]));
+    }
+
+    #[test]
+    fn walled_off_goal_is_unreachable() {
+        let grid = grid(&[(3, 0), (3, 1), (3, 2), (3, 3), (3, 4)]);
+        assert_eq!(grid.find_path(center(0, 0), center(4, 4)), None);
+        assert_eq!(grid.find_path(center(0, 0), center(3, 2)), None);
+    }
+
+    #[test]
+    fn diagonals_never_cut_corners() {
+        // the short way is a diagonal past the wall's corner
+        let grid = grid(&[(1, 0), (1, 1), (1, 2)]);
+        let from = center(0, 0);
+        let path = grid.find_path(from, center(2, 0)).unwrap();
+
+        let mut cell = grid.cell_at(from);
+        for waypoint in path {
+            let next = grid.cell_at(waypoint);
+            let step = next - cell;
+            assert!(step.x.abs() <= 1 && step.y.abs() <= 1, "{cell} to {next} skips cells");
+            assert!(grid.is_open(next));
+            assert!(
+                grid.is_open(cell + IVec2::new(step.x, 0)) && grid.is_open(cell + IVec2::new(0, step.y)),
+                "{cell} to {next} cuts a corner"
+            );
+            cell = next;
+        }
+        assert_eq!(cell, IVec2::new(2, 0));
+    }
+}
@@ -0,0 +1,131 @@
+use std::time::Duration;
+
+use bevy::prelude::*;
+use bevy_ecs_ldtk::ldtk::Definitions;
+use bevy_rapier2d::prelude::*;
+
+use crate::components::ColliderBundle;
+use crate::{PHYSICS_SCALE, TIMESTEP};
+
+/// Rapier stepping in [`FixedUpdate`], once per gameplay step
+///
+/// Everything that pushes bodies around should run in `FixedUpdate` before
+/// [`PhysicsSet::SyncBackend`] so a run plays out the same at any frame rate.
+pub struct PhysicsPlugin {
+    pub timestep: Duration,
+}
+
+impl Default for PhysicsPlugin {
+    fn default() -> Self {
+        PhysicsPlugin { timestep: Duration::from_secs_f32(TIMESTEP) }
+    }
+}
+
+impl Plugin for PhysicsPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(Time::<Fixed>::from_duration(self.timestep))
+            .insert_resource(RapierConfiguration {
+                gravity: Vec2::new(0.0, 0.0),
+                timestep_mode: TimestepMode::Fixed { dt: self.timestep.as_secs_f32(), substeps: 1 },
+                ..Default::default()
+            })
+            .add_plugins(RapierPhysicsPlugin::<NoUserData>::pixels_per_meter(PHYSICS_SCALE).in_fixed_schedule())
+            // rapier only writes back local transforms, so bring the global ones up to date before the next step
+            // rather than leaving it to PostUpdate, which doesn't run between steps packed into one frame
+            .add_systems(FixedUpdate, (
+                bevy::transform::systems::sync_simple_transforms,
+                bevy::transform::systems::propagate_transforms,
+            ).chain().after(PhysicsSet::Writeback));
+    }
+}
+
+/// The outline of a physics body, in pixels
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Shape {
+    Cuboid { half_width: f32, half_height: f32 },
+    Ball { radius: f32 },
+}
+
+/// How an entity collides and moves
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Body {
+    pub shape: Shape,
+    pub rigid_body: RigidBody,
+    pub friction: f32,
+    pub density: f32,
+    /// Keeps collisions from spinning the body; sprites still turn to face where they walk
+    pub lock_rotation: bool,
+}
+
+/// The physics an LDtk entity spawns with
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct PhysicsSpec {
+    pub identifier: &'static str,
+    /// `None` for entities that nothing collides with
+    pub body: Option<Body>,
+}
+
+/// Every LDtk entity the game knows about. Anything missing here fails [`missing_physics`].
+pub const PHYSICS: &[PhysicsSpec] = &[
+    PhysicsSpec {
+        identifier: "Raccoon",
+        body: Some(Body {
+            shape: Shape::Cuboid { half_width: 6.0, half_height: 14.0 },
+            rigid_body: RigidBody::Dynamic,
+            friction: 0.0,
+            density: 1.0,
+            lock_rotation: true,
+        }),
+    },
+    PhysicsSpec {
+        identifier: "Baddie",
+        body: Some(Body {
+            shape: Shape::Ball { radius: 6.0 },
+            rigid_body: RigidBody::Dynamic,
+            friction: 0.0,
+            density: 1.0,
+            lock_rotation: true,
+        }),
+    },
+    PhysicsSpec { identifier: "Trash", body: None },
+    PhysicsSpec { identifier: "Garbage", body: None },
+    PhysicsSpec { identifier: "Exit", body: None },
+    PhysicsSpec { identifier: "Focus", body: None },
+];
+
+pub fn spec(identifier: &str) -> Option<&'static PhysicsSpec> {
+    PHYSICS.iter().find(|spec| spec.identifier == identifier)
+}
+
+impl Body {
+    pub fn collider_bundle(&self) -> ColliderBundle {
+        let collider = match self.shape {
+            Shape::Cuboid { half_width, half_height } => Collider::cuboid(half_width, half_height),
+            Shape::Ball { radius } => Collider::ball(radius),
+        };
+        ColliderBundle {
+            collider,
+            rigid_body: self.rigid_body,
+            friction: Friction {
+                coefficient: self.friction,
+                combine_rule: CoefficientCombineRule::Min,
+            },
+            rotation_constraints: if self.lock_rotation {
+                LockedAxes::ROTATION_LOCKED
+            } else {
+                LockedAxes::empty()
+            },
+            density: ColliderMassProperties::Density(self.density),
+            ..Default::default()
+        }
+    }
+}
+
+/// Identifiers of the project's entities that have no entry in [`PHYSICS`]
+pub fn missing_physics(defs: &Definitions) -> Vec<String> {
+    defs.entities
+        .iter()
+        .filter(|def| spec(&def.identifier).is_none())
+        .map(|def| def.identifier.clone())
+        .collect()
+}
@@ -0,0 +1,452 @@
+//! Putting goodies down while planning
+//!
+//! The placer follows the mouse, the hotbar shows what's left, and every
+//! placement can be undone until the run starts.
+
+use bevy::{
+    input::mouse::MouseWheel,
+    prelude::*,
+    text::BreakLineOn,
+};
+use bevy_rapier2d::prelude::*;
+
+use crate::components::*;
+use crate::events::{GoodiePlaced, GoodieRemoved};
+use crate::items::ItemKind;
+use crate::nav::{nav_grid_at, NavGrid};
+use crate::replay::replaying;
+use crate::systems;
+use crate::tuning::Tunables;
+use crate::TIMESTEP;
+
+/// The placer, its hotbar and the path preview, active during [`GameState::Planning`]
+pub struct PlacementPlugin;
+
+impl Plugin for PlacementPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<PlacementHistory>()
+            .add_event::<GoodiePlaced>()
+            .add_event::<GoodieRemoved>()
+            .add_systems(Startup, spawn_placer)
+            .add_systems(OnEnter(GameState::MainMenu), hide_placer)
+            .add_systems(OnEnter(GameState::Focusing), clear_goodies)
+            .add_systems(OnEnter(GameState::Planning), show_placer)
+            .add_systems(OnEnter(GameState::Running), hide_placer)
+            .add_systems(OnEnter(GameState::GameWin), hide_placer)
+            .add_systems(Update, (
+                (start_run, update_placer, undo_redo, select_item).run_if(in_state(GameState::Planning).and_then(not(replaying))),
+                preview_path.run_if(in_state(GameState::Planning)),
+                update_count,
+                update_hotbar,
+            ));
+    }
+}
+
+fn spawn_placer(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+) {
+    commands.spawn(PlacerBundle {
+        placer: Placer,
+        sprite_sheet_bundle: GoodieBundle::spritesheet(&asset_server, Vec3::new(0.0, 0.0, 0.0), ItemKind::default()),
+        inventory: Inventory::default(),
+        selected: Selected::default(),
+    }).with_children(|parent| {
+        parent.spawn(Text2dBundle{
+            text: Text {
+                sections: vec![TextSection {
+                    value: "Remaining: 0".to_string(),
+                    style: TextStyle {
+                        font_size: 10.0,
+                        color: Color::WHITE,
+                        font: asset_server.load("fonts/PixelifySans-Bold.ttf"),
+                    },
+                }],
+                alignment: TextAlignment::Center,
+                linebreak_behavior: BreakLineOn::WordBoundary,
+            },
+            transform: Transform::from_xyz(0.0, -10.0, 0.0),
+            ..Default::default()
+        }).insert(PlacerText);
+    });
+    spawn_hotbar(&mut commands, &asset_server);
+}
+
+fn show_placer(
+    placer: Query<Entity, Or<(With<Placer>, With<Hotbar>)>>,
+    mut commands: Commands,
+) {
+    for placer in placer.iter() {
+        commands.entity(placer).insert(Visibility::Inherited);
+    }
+}
+
+/// Space sends the raccoon off to follow the plan
+fn start_run(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if keyboard_input.just_pressed(KeyCode::Space) {
+        next_state.set(GameState::Running);
+    }
+}
+
+/// Removes whatever the player placed during the last attempt
+fn clear_goodies(
+    goodies: Query<Entity, With<Goodie>>,
+    mut history: ResMut<PlacementHistory>,
+    mut commands: Commands,
+) {
+    for goodie in goodies.iter() {
+        commands.entity(goodie).despawn();
+    }
+    history.clear();
+}
+
+fn hide_placer(
+    placer: Query<Entity, Or<(With<Placer>, With<Hotbar>)>>,
+    mut commands: Commands,
+) {
+    for placer in placer.iter() {
+        commands.entity(placer).insert(Visibility::Hidden);
+    }
+}
+
+/// Whether the placer may put `kind` down on this level with the raccoon's current loot tier
+fn placeable(kind: ItemKind, stats: &HeroStats, rules: &LevelRules) -> bool {
+    rules.allows(kind) && kind.spec().tier <= stats.loot_tier()
+}
+
+/// Spawns a goodie if `kind` is allowed here and the placer has one left
+pub fn put_down(
+    inventory: &mut Inventory,
+    pos: Vec3,
+    kind: ItemKind,
+    stats: &HeroStats,
+    rules: &LevelRules,
+    asset_server: &Res<AssetServer>,
+    placed: &mut EventWriter<GoodiePlaced>,
+    commands: &mut Commands,
+) -> bool {
+    if !placeable(kind, stats, rules) || !inventory.take(kind) {
+        return false;
+    }
+    commands.spawn(GoodieBundle::new(asset_server, pos, kind));
+    placed.send(GoodiePlaced { kind, pos: pos.truncate() });
+    true
+}
+
+/// Removes the goodie of `kind` sitting at `pos` and refunds it
+pub fn pick_up(
+    inventory: &mut Inventory,
+    pos: Vec3,
+    kind: ItemKind,
+    goodies: &Query<(Entity, &Item, &Transform), (With<Goodie>, Without<Placer>)>,
+    removed: &mut EventWriter<GoodieRemoved>,
+    commands: &mut Commands,
+) -> bool {
+    let found = goodies
+        .iter()
+        .find(|(_, item, xform)| item.0 == kind && xform.translation.distance(pos) < 0.5);
+    let Some((goodie, _, _)) = found else {
+        return false;
+    };
+    commands.entity(goodie).despawn();
+    inventory.add(kind, 1);
+    removed.send(GoodieRemoved { kind, pos: pos.truncate() });
+    true
+}
+
+/// Left click places the selected goodie, right click picks up the one under the cursor
+fn update_placer(
+    wm: Res<WorldMouse>,
+    buttons: Res<Input<MouseButton>>,
+    tunables: Res<Tunables>,
+    heroes: Query<&HeroStats, With<Player>>,
+    rules: Res<LevelRules>,
+    mut placer: Query<(&mut Inventory, &Selected, &mut Transform), With<Placer>>,
+    goodies: Query<(Entity, &Item, &Transform), (With<Goodie>, Without<Placer>)>,
+    mut history: ResMut<PlacementHistory>,
+    asset_server: Res<AssetServer>,
+    mut placed: EventWriter<GoodiePlaced>,
+    mut removed: EventWriter<GoodieRemoved>,
+    mut commands: Commands,
+) {
+    if let Some(pos) = wm.pos {
+        if let Some((mut inventory, selected, mut placer)) = placer.iter_mut().next() {
+            let pos = pos.xy().extend(2.0);
+            placer.translation = pos;
+            let stats = heroes.iter().next();
+            if let (Some(kind), Some(stats)) = (selected.0.filter(|_| buttons.just_pressed(MouseButton::Left)), stats) {
+                if put_down(&mut inventory, pos, kind, stats, &rules, &asset_server, &mut placed, &mut commands) {
+                    history.push(PlacementAction::Place { pos, kind });
+                }
+            }
+            if buttons.just_pressed(MouseButton::Right) {
+                let nearest = goodies
+                    .iter()
+                    .map(|(_, item, xform)| (item.0, xform.translation))
+                    .filter(|(_, goodie_pos)| goodie_pos.xy().distance(pos.xy()) < tunables.pick_up_radius)
+                    .min_by(|(_, a), (_, b)| a.xy().distance(pos.xy()).total_cmp(&b.xy().distance(pos.xy())));
+                if let Some((kind, goodie_pos)) = nearest {
+                    if pick_up(&mut inventory, goodie_pos, kind, &goodies, &mut removed, &mut commands) {
+                        history.push(PlacementAction::PickUp { pos: goodie_pos, kind });
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Ctrl+Z steps back through this planning phase's placements, Ctrl+Y or Ctrl+Shift+Z steps forward again
+fn undo_redo(
+    keyboard_input: Res<Input<KeyCode>>,
+    heroes: Query<&HeroStats, With<Player>>,
+    rules: Res<LevelRules>,
+    mut history: ResMut<PlacementHistory>,
+    mut inventory: Query<&mut Inventory, With<Placer>>,
+    goodies: Query<(Entity, &Item, &Transform), (With<Goodie>, Without<Placer>)>,
+    asset_server: Res<AssetServer>,
+    mut placed: EventWriter<GoodiePlaced>,
+    mut removed: EventWriter<GoodieRemoved>,
+    mut commands: Commands,
+) {
+    let ctrl = keyboard_input.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight, KeyCode::SuperLeft, KeyCode::SuperRight]);
+    let shift = keyboard_input.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+    if !ctrl {
+        return;
+    }
+    let (Some(mut inventory), Some(stats)) = (inventory.iter_mut().next(), heroes.iter().next()) else {
+        return;
+    };
+    if keyboard_input.just_pressed(KeyCode::Z) && !shift {
+        match history.undo() {
+            Some(PlacementAction::Place { pos, kind }) => {
+                pick_up(&mut inventory, pos, kind, &goodies, &mut removed, &mut commands);
+            }
+            Some(PlacementAction::PickUp { pos, kind }) => {
+                put_down(&mut inventory, pos, kind, stats, &rules, &asset_server, &mut placed, &mut commands);
+            }
+            None => {}
+        }
+    } else if keyboard_input.just_pressed(KeyCode::Y) || keyboard_input.just_pressed(KeyCode::Z) {
+        match history.redo() {
+            Some(PlacementAction::Place { pos, kind }) => {
+                put_down(&mut inventory, pos, kind, stats, &rules, &asset_server, &mut placed, &mut commands);
+            }
+            Some(PlacementAction::PickUp { pos, kind }) => {
+                pick_up(&mut inventory, pos, kind, &goodies, &mut removed, &mut commands);
+            }
+            None => {}
+        }
+    }
+}
+
+/// Frames of the raccoon's run traced by the planning preview
+const PREVIEW_STEPS: usize = 60 * 20;
+
+/// Draws a ghost of the raccoon's run with what's placed so far, plus the goodie under the cursor
+fn preview_path(
+    rapier: Res<RapierContext>,
+    rules: Res<LevelRules>,
+    tunables: Res<Tunables>,
+    grids: Query<(&NavGrid, &GlobalTransform)>,
+    wm: Res<WorldMouse>,
+    player: Query<&GlobalTransform, With<Player>>,
+    attractors: Query<(&Attractor, &GlobalTransform, Has<Goal>)>,
+    placer: Query<(&Inventory, &Selected), With<Placer>>,
+    mut gizmos: Gizmos,
+) {
+    let Some(start) = player.iter().next() else {
+        return;
+    };
+    let mut placed: Vec<(Attractor, Vec2, bool)> = attractors
+        .iter()
+        .map(|(attractor, xform, is_goal)| (*attractor, xform.translation().truncate(), is_goal))
+        .collect();
+    if let (Some(pos), Some((inventory, Selected(Some(kind))))) = (wm.pos, placer.iter().next()) {
+        if inventory.count(*kind) > 0 {
+            placed.push((kind.attractor(), pos.truncate(), false));
+        }
+    }
+
+    let nav = if rules.pathfinding {
+        nav_grid_at(&grids, start.translation().truncate())
+    } else {
+        None
+    };
+    let path = systems::predict_path(
+        &rapier,
+        &rules,
+        &tunables,
+        nav,
+        start.translation().truncate(),
+        start.up().truncate(),
+        &placed,
+        TIMESTEP,
+        PREVIEW_STEPS,
+    );
+    let color = if path.reaches_goal {
+        Color::rgba(0.4, 1.0, 0.4, 0.6)
+    } else {
+        Color::rgba(1.0, 0.4, 0.4, 0.6)
+    };
+    if let Some(end) = path.points.last() {
+        gizmos.circle_2d(*end, 4.0, color);
+    }
+    gizmos.linestrip_2d(path.points, color);
+}
+
+const HOTBAR_KEYS: [KeyCode; 3] = [KeyCode::Key1, KeyCode::Key2, KeyCode::Key3];
+
+/// Number keys or the scroll wheel pick which catalog item to place, as far as the level and hero's loot tier allow
+fn select_item(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut scroll: EventReader<MouseWheel>,
+    heroes: Query<&HeroStats, With<Player>>,
+    rules: Res<LevelRules>,
+    mut placer: Query<(&mut Selected, &mut TextureAtlasSprite), With<Placer>>,
+) {
+    let Some(stats) = heroes.iter().next() else {
+        return;
+    };
+    let unlocked: Vec<ItemKind> = ItemKind::ALL
+        .into_iter()
+        .filter(|kind| placeable(*kind, stats, &rules))
+        .collect();
+    let steps: f32 = scroll.read().map(|wheel| wheel.y.signum()).sum();
+
+    for (mut selected, mut sprite) in placer.iter_mut() {
+        for (key, kind) in HOTBAR_KEYS.iter().zip(ItemKind::ALL) {
+            if keyboard_input.just_pressed(*key) && unlocked.contains(&kind) {
+                selected.0 = Some(kind);
+            }
+        }
+        if steps != 0.0 && !unlocked.is_empty() {
+            let current = unlocked.iter().position(|kind| Some(*kind) == selected.0).unwrap_or(0) as i32;
+            let next = (current - steps as i32).rem_euclid(unlocked.len() as i32);
+            selected.0 = Some(unlocked[next as usize]);
+        }
+        // a restart or a new level can take away items the raccoon had
+        if !selected.0.is_some_and(|kind| unlocked.contains(&kind)) {
+            selected.0 = unlocked.first().copied();
+        }
+        match selected.0 {
+            Some(kind) => {
+                sprite.index = kind.spec().sprite_index;
+                sprite.color = Color::WHITE;
+            }
+            None => sprite.color = Color::NONE,
+        }
+    }
+}
+
+#[derive(Component)]
+struct PlacerText;
+
+fn update_count(
+    mut query: Query<(&Parent, &mut Text), With<PlacerText>>,
+    placers: Query<(&Inventory, &Selected), With<Placer>>,
+) {
+    for (parent, mut text) in query.iter_mut() {
+        let (inventory, selected) = placers.get(**parent).unwrap();
+        text.sections[0].value = match selected.0 {
+            Some(kind) => format!("{}: {}", kind.spec().name, inventory.count(kind)),
+            None => "Nothing to place".to_string(),
+        };
+    }
+}
+
+/// The row of item slots along the bottom of the screen while planning
+#[derive(Component)]
+struct Hotbar;
+
+#[derive(Component)]
+struct HotbarSlot(ItemKind);
+
+#[derive(Component)]
+struct HotbarLabel(ItemKind);
+
+fn spawn_hotbar(
+    commands: &mut Commands,
+    asset_server: &AssetServer,
+) {
+    let texture_atlas = GoodieBundle::texture_atlas(asset_server);
+    let font = asset_server.load("fonts/PixelifySans-Bold.ttf");
+
+    commands.spawn(NodeBundle {
+        style: Style {
+            position_type: PositionType::Absolute,
+            bottom: Val::Px(10.0),
+            width: Val::Percent(100.0),
+            justify_content: JustifyContent::Center,
+            ..default()
+        },
+        visibility: Visibility::Hidden,
+        ..default()
+    }).insert(Hotbar).with_children(|bar| {
+        for kind in ItemKind::ALL {
+            bar.spawn(NodeBundle {
+                style: Style {
+                    flex_direction: FlexDirection::Column,
+                    align_items: AlignItems::Center,
+                    margin: UiRect::horizontal(Val::Px(4.0)),
+                    padding: UiRect::all(Val::Px(4.0)),
+                    ..default()
+                },
+                ..default()
+            }).insert(HotbarSlot(kind)).with_children(|slot| {
+                slot.spawn(AtlasImageBundle {
+                    style: Style {
+                        width: Val::Px(48.0),
+                        height: Val::Px(48.0),
+                        ..default()
+                    },
+                    texture_atlas: texture_atlas.clone(),
+                    texture_atlas_image: UiTextureAtlasImage {
+                        index: kind.spec().sprite_index,
+                        ..default()
+                    },
+                    ..default()
+                });
+                slot.spawn(TextBundle::from_section("", TextStyle {
+                    font_size: 16.0,
+                    color: Color::WHITE,
+                    font: font.clone(),
+                })).insert(HotbarLabel(kind));
+            });
+        }
+    });
+}
+
+fn update_hotbar(
+    heroes: Query<&HeroStats, With<Player>>,
+    rules: Res<LevelRules>,
+    placer: Query<(&Inventory, &Selected), With<Placer>>,
+    mut slots: Query<(&HotbarSlot, &mut BackgroundColor)>,
+    mut labels: Query<(&HotbarLabel, &mut Text)>,
+) {
+    let (Some((inventory, selected)), Some(stats)) = (placer.iter().next(), heroes.iter().next()) else {
+        return;
+    };
+    for (slot, mut background) in slots.iter_mut() {
+        background.0 = if Some(slot.0) == selected.0 {
+            Color::rgba(1.0, 1.0, 1.0, 0.4)
+        } else if !placeable(slot.0, stats, &rules) {
+            Color::rgba(0.0, 0.0, 0.0, 0.7)
+        } else {
+            Color::rgba(0.0, 0.0, 0.0, 0.3)
+        };
+    }
+    for (label, mut text) in labels.iter_mut() {
+        let key = ItemKind::ALL.iter().position(|kind| *kind == label.0).unwrap_or(0) + 1;
+        let spec = label.0.spec();
+        text.sections[0].value = if !rules.allows(label.0) {
+            format!("{}: -", key)
+        } else if spec.tier > stats.loot_tier() {
+            format!("{}: tier {}", key, spec.tier)
+        } else {
+            format!("{}: {} x{}", key, spec.name, inventory.count(label.0))
+        };
+    }
+}
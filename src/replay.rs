@@ -0,0 +1,514 @@
+//! Recording level attempts and playing them back
+//!
+//! Every run the player starts is kept as a [`Replay`]: the level, what the
+//! placer and the raccoon started with, each placement and pick-up with when
+//! it happened during planning, and when the run began. Runs step on a fixed
+//! timestep, so feeding those inputs back through the same systems plays the
+//! attempt out exactly as before. Press L to watch the last attempt at the
+//! current level, or hand a replay file to the `simulate` binary.
+
+use bevy::prelude::*;
+use bevy_ecs_ldtk::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::components::*;
+use crate::events::{GoodiePlaced, GoodieRemoved};
+use crate::items::{ItemKind, Placement};
+use crate::level::{can_retry, reset_level, retry_level, selected_identifier};
+use crate::placement::{pick_up, put_down};
+use crate::storage;
+use crate::tuning::Tunables;
+
+/// Bumped whenever [`Replay`] changes shape
+pub const REPLAY_FORMAT: u32 = 1;
+
+/// How much faster than real time holding the right arrow plays
+const FAST_FORWARD: f32 = 4.0;
+/// How fast playback catches up to a rewound point
+const SEEK_SPEED: f32 = 8.0;
+/// Seconds of the run the left arrow steps back
+const REWIND_SECONDS: f32 = 5.0;
+/// Each jumps to its number of seconds into the run
+const SEEK_KEYS: [KeyCode; 10] = [
+    KeyCode::Key0,
+    KeyCode::Key1,
+    KeyCode::Key2,
+    KeyCode::Key3,
+    KeyCode::Key4,
+    KeyCode::Key5,
+    KeyCode::Key6,
+    KeyCode::Key7,
+    KeyCode::Key8,
+    KeyCode::Key9,
+];
+/// Attempts per level kept natively besides the last one, newest first
+#[cfg(not(target_arch = "wasm32"))]
+const KEPT_ATTEMPTS: usize = 10;
+
+/// One attempt at a level, from the start of planning to the start of the run
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Replay {
+    pub format: u32,
+    /// LDtk identifier of the level
+    pub level: String,
+    /// [`Tunables::version`] the attempt was made with
+    pub tunables_version: u32,
+    /// Seed for anything random in a run; nothing is yet, so this is always 0
+    pub seed: u64,
+    /// What the placer had when planning began
+    pub inventory: Inventory,
+    /// The raccoon as it was when planning began
+    pub hero: HeroStats,
+    pub actions: Vec<TimedAction>,
+    /// Seconds into planning that the run started
+    pub started: f32,
+}
+
+/// A placement or pick-up and when it happened
+#[derive(Copy, Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct TimedAction {
+    /// Seconds into planning
+    pub time: f32,
+    pub action: PlacementAction,
+}
+
+impl Replay {
+    pub fn from_json(json: &str) -> Result<Self, serde_json::Error> {
+        serde_json::from_str(json)
+    }
+
+    /// The goodies that were down when the run started
+    pub fn placements(&self) -> Vec<Placement> {
+        let mut placed: Vec<(Vec3, ItemKind)> = Vec::new();
+        for step in &self.actions {
+            match step.action {
+                PlacementAction::Place { pos, kind } => placed.push((pos, kind)),
+                PlacementAction::PickUp { pos, kind } => {
+                    if let Some(i) = placed.iter().position(|(at, k)| *k == kind && at.distance(pos) < 0.5) {
+                        placed.remove(i);
+                    }
+                }
+            }
+        }
+        placed
+            .into_iter()
+            .map(|(pos, kind)| Placement { pos: pos.truncate(), kind })
+            .collect()
+    }
+
+    /// Keeps this as the last attempt at its level, warning rather than failing if it can't be
+    ///
+    /// Natively the last [`KEPT_ATTEMPTS`] attempts are also kept, for attaching to bug reports:
+    /// `replays/<level>-1` is the newest, and storing another drops the oldest.
+    pub fn store(&self) {
+        let json = match serde_json::to_string(self) {
+            Ok(json) => json,
+            Err(e) => {
+                warn!("couldn't write replay: {e}");
+                return;
+            }
+        };
+        if let Err(e) = storage::write(&last_name(&self.level), &json) {
+            warn!("couldn't write replay: {e}");
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Err(e) = keep_attempt(&self.level, &json) {
+            warn!("couldn't keep replay: {e}");
+        }
+    }
+
+    /// The last attempt at the level with identifier `level`, if there is a readable one
+    pub fn load_last(level: &str) -> Option<Self> {
+        let json = storage::read(&last_name(level))?;
+        Replay::from_json(&json)
+            .map_err(|e| warn!("ignoring unreadable replay: {e}"))
+            .ok()
+    }
+}
+
+fn last_name(level: &str) -> String {
+    format!("replays/{level}")
+}
+
+/// Moves each kept attempt at `level` one slot older, dropping the oldest, and keeps `json` as the newest
+#[cfg(not(target_arch = "wasm32"))]
+fn keep_attempt(level: &str, json: &str) -> Result<(), String> {
+    let kept_name = |slot: usize| format!("replays/{level}-{slot}");
+    for slot in (1..KEPT_ATTEMPTS).rev() {
+        if let Some(older) = storage::read(&kept_name(slot)) {
+            storage::write(&kept_name(slot + 1), &older)?;
+        }
+    }
+    storage::write(&kept_name(1), json)
+}
+
+/// The placements of the planning phase underway, for the next [`Replay`]
+#[derive(Resource, Clone, Debug, Default)]
+struct ReplayRecorder {
+    /// Elapsed game time when planning began
+    planning_started: f32,
+    actions: Vec<TimedAction>,
+}
+
+/// The replay being watched, if any
+#[derive(Resource, Clone, Debug, Default)]
+pub struct Playback {
+    replay: Option<Replay>,
+    /// The player's own snapshot, put back when playback stops
+    own_snapshot: Option<LevelSnapshot>,
+    /// Index of the next action to play
+    next: usize,
+    /// Seconds into the run to fast-forward to before pausing
+    seek: Option<f32>,
+}
+
+impl Playback {
+    pub fn is_playing(&self) -> bool {
+        self.replay.is_some()
+    }
+}
+
+/// Run condition for while a replay is being watched
+pub fn replaying(playback: Res<Playback>) -> bool {
+    playback.is_playing()
+}
+
+/// Records every run the player starts, and plays back the last one on request
+pub struct ReplayPlugin;
+
+impl Plugin for ReplayPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ReplayRecorder>()
+            .init_resource::<Playback>()
+            .add_systems(Startup, spawn_replay_text)
+            .add_systems(OnEnter(GameState::MainMenu), stop_playback)
+            .add_systems(OnEnter(GameState::Planning), start_planning_clock)
+            .add_systems(OnEnter(GameState::Running), (
+                save_replay.run_if(not(replaying)),
+                begin_seek.run_if(replaying),
+            ))
+            .add_systems(Update, (
+                watch_last_replay.run_if(
+                    in_state(GameState::Planning).or_else(in_state(GameState::GameLose)).or_else(in_state(GameState::Results)),
+                ),
+                play_placements.run_if(in_state(GameState::Planning).and_then(replaying)),
+                // a seek only starts counting once the level has gone back to planning
+                (stop_on_retry.run_if(can_retry).before(retry_level), finish_seek, playback_controls).chain().run_if(replaying),
+                update_replay_text,
+            ))
+            // after the placer's systems, so a placement on the frame the run starts still counts
+            .add_systems(PostUpdate, record_placements.run_if(in_state(GameState::Planning).and_then(not(replaying))));
+    }
+}
+
+fn start_planning_clock(
+    time: Res<Time>,
+    mut recorder: ResMut<ReplayRecorder>,
+    mut playback: ResMut<Playback>,
+) {
+    recorder.planning_started = time.elapsed_seconds();
+    recorder.actions.clear();
+    playback.next = 0;
+}
+
+fn record_placements(
+    time: Res<Time>,
+    mut placed: EventReader<GoodiePlaced>,
+    mut removed: EventReader<GoodieRemoved>,
+    mut recorder: ResMut<ReplayRecorder>,
+) {
+    let now = time.elapsed_seconds() - recorder.planning_started;
+    // goodies sit at the same height the placer puts them
+    let placed = placed
+        .read()
+        .map(|event| PlacementAction::Place { pos: event.pos.extend(2.0), kind: event.kind });
+    let removed = removed
+        .read()
+        .map(|event| PlacementAction::PickUp { pos: event.pos.extend(2.0), kind: event.kind });
+    let actions: Vec<TimedAction> = placed
+        .chain(removed)
+        .map(|action| TimedAction { time: now, action })
+        .collect();
+    recorder.actions.extend(actions);
+}
+
+/// Writes out the attempt that's just starting, so it's kept even if the run never finishes
+fn save_replay(
+    time: Res<Time>,
+    recorder: Res<ReplayRecorder>,
+    snapshot: Res<LevelSnapshot>,
+    tunables: Res<Tunables>,
+    level: Res<LevelSelection>,
+    projects: Query<&Handle<LdtkProject>>,
+    project_assets: Res<Assets<LdtkProject>>,
+) {
+    let Some(identifier) = selected_identifier(&level, &projects, &project_assets) else {
+        return;
+    };
+    Replay {
+        format: REPLAY_FORMAT,
+        level: identifier,
+        tunables_version: tunables.version,
+        seed: 0,
+        inventory: snapshot.inventory.clone(),
+        hero: snapshot.stats.clone(),
+        actions: recorder.actions.clone(),
+        started: time.elapsed_seconds() - recorder.planning_started,
+    }
+    .store();
+}
+
+/// L plays the last attempt at the current level from the start, or starts it over if it's already playing
+fn watch_last_replay(
+    keyboard_input: Res<Input<KeyCode>>,
+    tunables: Res<Tunables>,
+    mut playback: ResMut<Playback>,
+    mut virtual_time: ResMut<Time<Virtual>>,
+    mut snapshot: ResMut<LevelSnapshot>,
+    mut inventory: Query<&mut Inventory, With<Placer>>,
+    mut next_state: ResMut<NextState<GameState>>,
+    level: Res<LevelSelection>,
+    projects: Query<&Handle<LdtkProject>>,
+    project_assets: Res<Assets<LdtkProject>>,
+    levels: Query<(Entity, &LevelIid)>,
+    mut commands: Commands,
+) {
+    if !keyboard_input.just_pressed(KeyCode::L) {
+        return;
+    }
+    let Some(identifier) = selected_identifier(&level, &projects, &project_assets) else {
+        return;
+    };
+    let Some(replay) = Replay::load_last(&identifier) else {
+        info!("no attempt at {identifier} recorded yet");
+        return;
+    };
+    if replay.tunables_version != tunables.version {
+        warn!(
+            "replay was recorded with tuning version {}, playing with {}; it may not play out the same",
+            replay.tunables_version, tunables.version
+        );
+    }
+
+    if playback.replay.is_none() {
+        playback.own_snapshot = Some(snapshot.clone());
+    }
+    *snapshot = LevelSnapshot {
+        inventory: replay.inventory.clone(),
+        stats: replay.hero.clone(),
+    };
+    playback.replay = Some(replay);
+    playback.seek = None;
+    virtual_time.set_relative_speed(1.0);
+    virtual_time.unpause();
+    reset_level(&snapshot, &mut inventory, &mut next_state, &level, &projects, &project_assets, &levels, &mut commands);
+}
+
+/// Repeats the recorded placements at the times they were made, then starts the run when the player did
+///
+/// Plays at most one action a frame so a pick-up can find a goodie put down just before it.
+/// While seeking it doesn't wait for the recorded times.
+fn play_placements(
+    time: Res<Time>,
+    recorder: Res<ReplayRecorder>,
+    mut playback: ResMut<Playback>,
+    heroes: Query<&HeroStats, With<Player>>,
+    rules: Res<LevelRules>,
+    mut placer: Query<&mut Inventory, With<Placer>>,
+    goodies: Query<(Entity, &Item, &Transform), (With<Goodie>, Without<Placer>)>,
+    asset_server: Res<AssetServer>,
+    mut placed: EventWriter<GoodiePlaced>,
+    mut removed: EventWriter<GoodieRemoved>,
+    mut next_state: ResMut<NextState<GameState>>,
+    mut commands: Commands,
+) {
+    let playback = &mut *playback;
+    let Some(replay) = &playback.replay else {
+        return;
+    };
+    let (Some(mut inventory), Some(stats)) = (placer.iter_mut().next(), heroes.iter().next()) else {
+        return;
+    };
+    let clock = time.elapsed_seconds() - recorder.planning_started;
+    let seeking = playback.seek.is_some();
+
+    match replay.actions.get(playback.next) {
+        Some(step) if seeking || step.time <= clock => {
+            match step.action {
+                PlacementAction::Place { pos, kind } => {
+                    put_down(&mut inventory, pos, kind, stats, &rules, &asset_server, &mut placed, &mut commands);
+                }
+                PlacementAction::PickUp { pos, kind } => {
+                    pick_up(&mut inventory, pos, kind, &goodies, &mut removed, &mut commands);
+                }
+            }
+            playback.next += 1;
+        }
+        Some(_) => {}
+        None if seeking || clock >= replay.started => next_state.set(GameState::Running),
+        None => {}
+    }
+}
+
+/// P pauses, holding the right arrow fast-forwards, the left arrow steps the run back a few seconds
+/// and the number keys jump to that many seconds into the run
+///
+/// Runs can't be stepped backwards, so every jump plays the level again from
+/// the start at [`SEEK_SPEED`] until it reaches the chosen time.
+fn playback_controls(
+    keyboard_input: Res<Input<KeyCode>>,
+    state: Res<State<GameState>>,
+    clock: Res<RunClock>,
+    mut virtual_time: ResMut<Time<Virtual>>,
+    mut playback: ResMut<Playback>,
+    snapshot: Res<LevelSnapshot>,
+    mut inventory: Query<&mut Inventory, With<Placer>>,
+    mut next_state: ResMut<NextState<GameState>>,
+    level: Res<LevelSelection>,
+    projects: Query<&Handle<LdtkProject>>,
+    project_assets: Res<Assets<LdtkProject>>,
+    levels: Query<(Entity, &LevelIid)>,
+    mut commands: Commands,
+) {
+    if keyboard_input.just_pressed(KeyCode::P) {
+        if virtual_time.is_paused() {
+            virtual_time.unpause();
+        } else {
+            virtual_time.pause();
+        }
+    }
+    if playback.seek.is_some() {
+        return;
+    }
+    let speed = if keyboard_input.pressed(KeyCode::Right) { FAST_FORWARD } else { 1.0 };
+    virtual_time.set_relative_speed(speed);
+
+    let target = if keyboard_input.just_pressed(KeyCode::Left) {
+        // the run clock only means something once the run has started
+        match state.get() {
+            GameState::Focusing | GameState::Planning => Some(0.0),
+            _ => Some((clock.0 - REWIND_SECONDS).max(0.0)),
+        }
+    } else {
+        SEEK_KEYS
+            .iter()
+            .position(|key| keyboard_input.just_pressed(*key))
+            .map(|seconds| seconds as f32)
+    };
+    if let Some(target) = target {
+        playback.seek = Some(target);
+        virtual_time.unpause();
+        reset_level(&snapshot, &mut inventory, &mut next_state, &level, &projects, &project_assets, &levels, &mut commands);
+    }
+}
+
+fn begin_seek(
+    playback: Res<Playback>,
+    mut virtual_time: ResMut<Time<Virtual>>,
+) {
+    if playback.seek.is_some() {
+        virtual_time.set_relative_speed(SEEK_SPEED);
+    }
+}
+
+/// Pauses once a seek reaches its point in the run, or the run ends short of it
+fn finish_seek(
+    state: Res<State<GameState>>,
+    clock: Res<RunClock>,
+    mut playback: ResMut<Playback>,
+    mut virtual_time: ResMut<Time<Virtual>>,
+) {
+    let Some(target) = playback.seek else {
+        return;
+    };
+    let arrived = match state.get() {
+        GameState::Focusing | GameState::Planning => false,
+        GameState::Running => clock.0 >= target,
+        _ => true,
+    };
+    if arrived {
+        playback.seek = None;
+        virtual_time.set_relative_speed(1.0);
+        virtual_time.pause();
+    }
+}
+
+/// R leaves the replay and retries the level with the player's own raccoon
+fn stop_on_retry(
+    keyboard_input: Res<Input<KeyCode>>,
+    playback: ResMut<Playback>,
+    snapshot: ResMut<LevelSnapshot>,
+    virtual_time: ResMut<Time<Virtual>>,
+) {
+    if keyboard_input.just_pressed(KeyCode::R) {
+        stop_playback(playback, snapshot, virtual_time);
+    }
+}
+
+fn stop_playback(
+    mut playback: ResMut<Playback>,
+    mut snapshot: ResMut<LevelSnapshot>,
+    mut virtual_time: ResMut<Time<Virtual>>,
+) {
+    if let Some(own) = playback.own_snapshot.take() {
+        *snapshot = own;
+    }
+    *playback = Playback::default();
+    virtual_time.set_relative_speed(1.0);
+    virtual_time.unpause();
+}
+
+#[derive(Component)]
+struct ReplayText;
+
+fn spawn_replay_text(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+) {
+    commands.spawn(TextBundle {
+        text: Text::from_section("", TextStyle {
+            font_size: 20.0,
+            color: Color::WHITE,
+            font: asset_server.load("fonts/PixelifySans-Bold.ttf"),
+        }).with_alignment(TextAlignment::Right),
+        style: Style {
+            position_type: PositionType::Absolute,
+            top: Val::Px(10.0),
+            right: Val::Px(10.0),
+            ..default()
+        },
+        visibility: Visibility::Hidden,
+        ..default()
+    }).insert(ReplayText);
+}
+
+fn update_replay_text(
+    playback: Res<Playback>,
+    state: Res<State<GameState>>,
+    clock: Res<RunClock>,
+    virtual_time: Res<Time<Virtual>>,
+    mut text: Query<(&mut Text, &mut Visibility), With<ReplayText>>,
+) {
+    for (mut text, mut visibility) in text.iter_mut() {
+        let Some(replay) = &playback.replay else {
+            *visibility = Visibility::Hidden;
+            continue;
+        };
+        *visibility = Visibility::Inherited;
+        let at = match state.get() {
+            GameState::Focusing | GameState::Planning => "planning".to_string(),
+            _ => format!("{:.1}s", clock.0),
+        };
+        let status = if playback.seek.is_some() {
+            "seeking"
+        } else if virtual_time.is_paused() {
+            "paused"
+        } else {
+            "playing"
+        };
+        text.sections[0].value = format!(
+            "Replay of {}: {} ({})\nP pause, hold \u{2192} fast forward, \u{2190} back {}s, 0-9 jump to second, L restart, R stop",
+            replay.level, at, status, REWIND_SECONDS
+        );
+    }
+}
@@ -0,0 +1,87 @@
+//! Progress that outlives the game window
+//!
+//! [`SaveData`] is kept as JSON in [`storage`](crate::storage), in a file in
+//! the user's data directory or in `localStorage` when running in a browser.
+//! Levels are keyed by their LDtk identifier so reordering the project doesn't
+//! scramble anyone's records.
+
+use std::collections::{BTreeSet, HashMap};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::score::LevelScore;
+use crate::storage;
+
+/// Name of the save in [`storage`]
+const SAVE_NAME: &str = "save";
+
+/// The best a level has been cleared
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+pub struct Best {
+    pub points: u32,
+    pub stars: u32,
+}
+
+/// Player preferences that stick between sessions
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    /// Mark every attractor the raccoon can currently see
+    pub show_visible: bool,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings { show_visible: true }
+    }
+}
+
+#[derive(Resource, Clone, PartialEq, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SaveData {
+    /// Identifiers of the levels that can be played; the first level always can
+    pub unlocked: BTreeSet<String>,
+    pub best: HashMap<String, Best>,
+    pub settings: Settings,
+}
+
+impl SaveData {
+    /// Reads the save, starting afresh if there isn't one or it can't be read
+    pub fn load() -> Self {
+        let Some(json) = storage::read(SAVE_NAME) else {
+            return SaveData::default();
+        };
+        serde_json::from_str(&json).unwrap_or_else(|e| {
+            warn!("ignoring unreadable save data: {e}");
+            SaveData::default()
+        })
+    }
+
+    /// Writes the save out, warning rather than failing if it can't be
+    pub fn store(&self) {
+        let result = serde_json::to_string_pretty(self)
+            .map_err(|e| e.to_string())
+            .and_then(|json| storage::write(SAVE_NAME, &json));
+        if let Err(e) = result {
+            warn!("couldn't write save data: {e}");
+        }
+    }
+
+    pub fn is_unlocked(&self, index: usize, identifier: &str) -> bool {
+        index == 0 || self.unlocked.contains(identifier)
+    }
+
+    pub fn unlock(&mut self, identifier: &str) {
+        self.unlocked.insert(identifier.to_string());
+    }
+
+    /// Keeps `score` if it beats the level's best, returning whether it did
+    pub fn record(&mut self, identifier: &str, score: &LevelScore) -> bool {
+        let best = self.best.entry(identifier.to_string()).or_default();
+        let improved = score.points > best.points || score.stars > best.stars;
+        best.points = best.points.max(score.points);
+        best.stars = best.stars.max(score.stars);
+        improved
+    }
+}
@@ -0,0 +1,140 @@
+//! Scoring a cleared level
+//!
+//! A run earns points for reaching the goal, more for every goodie left in
+//! the placer, for getting there quickly and for garbage eaten along the way.
+//! Stars only look at how many goodies it took against the level's par.
+
+use bevy::prelude::*;
+
+use crate::components::Inventory;
+
+/// Points for clearing a level at all
+pub const CLEAR_POINTS: u32 = 1000;
+/// Points for each goodie of the budget left unplaced
+pub const SAVED_GOODIE_POINTS: u32 = 100;
+/// Points for each bit of the level's own garbage the raccoon ate
+pub const GARBAGE_POINTS: u32 = 50;
+/// Points for reaching the goal instantly, dwindling by [`TIME_POINTS_PER_SECOND`]
+pub const TIME_POINTS: u32 = 500;
+pub const TIME_POINTS_PER_SECOND: f32 = 25.0;
+
+/// How the last cleared level went
+#[derive(Resource, Copy, Clone, PartialEq, Debug, Default)]
+pub struct LevelScore {
+    /// Goodies placed for the run
+    pub used: u32,
+    /// Goodies the placer had to work with
+    pub budget: u32,
+    /// Seconds from starting the run to reaching the goal
+    pub time: f32,
+    pub garbage: u32,
+    pub par: Option<u32>,
+    pub points: u32,
+    /// 1 to 3
+    pub stars: u32,
+}
+
+impl LevelScore {
+    pub fn new(used: u32, budget: u32, time: f32, garbage: u32, par: Option<u32>) -> Self {
+        let time_points = (TIME_POINTS as f32 - time * TIME_POINTS_PER_SECOND).max(0.0) as u32;
+        LevelScore {
+            used,
+            budget,
+            time,
+            garbage,
+            par,
+            points: CLEAR_POINTS
+                + budget.saturating_sub(used) * SAVED_GOODIE_POINTS
+                + time_points
+                + garbage * GARBAGE_POINTS,
+            stars: stars(used, par),
+        }
+    }
+
+    /// Scores a clear from what the placer started the run with and what it has left
+    ///
+    /// Loot the raccoon brought from earlier fights can be placed like any
+    /// goodie, but only the level's own `budget` earns points for going unused.
+    /// The game's results screen and the simulator both score through this.
+    pub fn from_placer(
+        budget: &Inventory,
+        start: &Inventory,
+        left: &Inventory,
+        time: f32,
+        garbage: u32,
+        par: Option<u32>,
+    ) -> Self {
+        LevelScore::new(start.total().saturating_sub(left.total()), budget.total(), time, garbage, par)
+    }
+}
+
+/// Three stars at or under par, two for a little over, one for anything else that clears the level
+///
+/// Levels without a par give three stars to any clear.
+pub fn stars(used: u32, par: Option<u32>) -> u32 {
+    match par {
+        None => 3,
+        Some(par) if used <= par => 3,
+        Some(par) if used <= par + (par / 2).max(1) => 2,
+        Some(_) => 1,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::items::ItemKind;
+
+    fn hot_dogs(count: u32) -> Inventory {
+        Inventory::new(&[(ItemKind::HotDog, count)])
+    }
+
+    #[test]
+    fn unused_loot_scores_nothing() {
+        let budget = hot_dogs(3);
+        let without_loot = LevelScore::from_placer(&budget, &hot_dogs(3), &hot_dogs(1), 10.0, 0, Some(2));
+        let with_loot = LevelScore::from_placer(&budget, &hot_dogs(5), &hot_dogs(3), 10.0, 0, Some(2));
+        assert_eq!(with_loot, without_loot);
+    }
+
+    #[test]
+    fn placed_loot_counts_against_the_budget() {
+        let score = LevelScore::from_placer(&hot_dogs(3), &hot_dogs(5), &hot_dogs(0), 10.0, 0, None);
+        assert_eq!(score.used, 5);
+        assert_eq!(score.points, CLEAR_POINTS + 250);
+    }
+
+    #[test]
+    fn stars_drop_past_par_and_half_again() {
+        assert_eq!(stars(4, Some(4)), 3);
+        assert_eq!(stars(5, Some(4)), 2);
+        assert_eq!(stars(6, Some(4)), 2);
+        assert_eq!(stars(7, Some(4)), 1);
+        // half of an odd par rounds down
+        assert_eq!(stars(7, Some(5)), 2);
+        assert_eq!(stars(8, Some(5)), 1);
+    }
+
+    #[test]
+    fn small_pars_allow_one_extra_goodie_for_two_stars() {
+        assert_eq!(stars(0, Some(0)), 3);
+        assert_eq!(stars(1, Some(0)), 2);
+        assert_eq!(stars(2, Some(0)), 1);
+        assert_eq!(stars(1, Some(1)), 3);
+        assert_eq!(stars(2, Some(1)), 2);
+        assert_eq!(stars(3, Some(1)), 1);
+    }
+
+    #[test]
+    fn no_par_always_earns_three_stars() {
+        assert_eq!(stars(100, None), 3);
+    }
+
+    #[test]
+    fn time_points_never_go_negative() {
+        let seconds_to_zero = TIME_POINTS as f32 / TIME_POINTS_PER_SECOND;
+        assert_eq!(LevelScore::new(0, 0, 0.0, 0, None).points, CLEAR_POINTS + TIME_POINTS);
+        assert_eq!(LevelScore::new(0, 0, seconds_to_zero, 0, None).points, CLEAR_POINTS);
+        assert_eq!(LevelScore::new(0, 0, seconds_to_zero * 3.0, 0, None).points, CLEAR_POINTS);
+    }
+}
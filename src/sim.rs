@@ -0,0 +1,404 @@
+//! Headless, deterministic simulation of a level's Running phase
+//!
+//! This builds a windowless [`App`] with only what the raccoon needs to make
+//! decisions: the level's walls, goal and garbage read straight from the LDtk
+//! project, a list of placed attractors, Rapier on a fixed timestep and the
+//! same [`HeroPlugin`] the game runs. Use it to
+//! batch-evaluate placements or to pin down behaviour in regression checks.
+
+use std::collections::HashSet;
+use std::fmt;
+use std::path::Path;
+use std::time::Duration;
+
+use bevy::prelude::*;
+use bevy::time::TimeUpdateStrategy;
+use bevy_ecs_ldtk::ldtk::{LdtkJson, Level};
+use bevy_ecs_ldtk::prelude::*;
+use bevy_ecs_ldtk::utils::{int_grid_index_to_grid_coords, ldtk_pixel_coords_to_translation, ldtk_pixel_coords_to_translation_pivoted};
+
+use crate::components::*;
+use crate::items::{ItemKind, Placement};
+use crate::nav::{Memory, NavGrid};
+use crate::physics::{missing_physics, PhysicsPlugin};
+use crate::score::LevelScore;
+use crate::hero::HeroPlugin;
+use crate::replay::Replay;
+use crate::tuning::{Tunables, TuningError};
+use crate::systems::merge_wall_rects;
+use crate::TIMESTEP;
+
+/// Length of one simulation tick, in seconds, the same as the game's
+pub const DEFAULT_TIMESTEP: f32 = TIMESTEP;
+
+/// IntGrid value of the wall tiles, matching the `WallBundle` registration
+const WALL_VALUE: i32 = 2;
+
+#[derive(Debug)]
+pub enum SimulationError {
+    Io(std::io::Error),
+    Parse(serde_json::Error),
+    MissingLevel(usize),
+    MissingEntity(&'static str),
+    /// Entities the project defines that [`PHYSICS`](crate::physics::PHYSICS) doesn't cover
+    MissingPhysics(Vec<String>),
+    Tuning(TuningError),
+    Replay(serde_json::Error),
+    /// A replay names a level the project doesn't have
+    UnknownLevel(String),
+}
+
+impl fmt::Display for SimulationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SimulationError::Io(e) => write!(f, "could not read project: {e}"),
+            SimulationError::Parse(e) => write!(f, "could not parse project: {e}"),
+            SimulationError::MissingLevel(level) => write!(f, "project has no level {level}"),
+            SimulationError::MissingEntity(identifier) => write!(f, "level has no {identifier} entity"),
+            SimulationError::MissingPhysics(identifiers) => {
+                write!(f, "no physics definition for {}", identifiers.join(", "))
+            }
+            SimulationError::Tuning(e) => e.fmt(f),
+            SimulationError::Replay(e) => write!(f, "could not parse replay: {e}"),
+            SimulationError::UnknownLevel(identifier) => write!(f, "project has no level {identifier}"),
+        }
+    }
+}
+
+impl std::error::Error for SimulationError {}
+
+impl From<std::io::Error> for SimulationError {
+    fn from(e: std::io::Error) -> Self {
+        SimulationError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for SimulationError {
+    fn from(e: serde_json::Error) -> Self {
+        SimulationError::Parse(e)
+    }
+}
+
+impl From<TuningError> for SimulationError {
+    fn from(e: TuningError) -> Self {
+        SimulationError::Tuning(e)
+    }
+}
+
+/// How a simulated run ended
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Outcome {
+    /// The raccoon reached the goal
+    Win,
+    /// The raccoon starved, got caught or ran out of time
+    Lose(LoseCause),
+    /// Neither happened before the tick limit
+    TickLimit,
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct SimulationReport {
+    pub outcome: Outcome,
+    pub ticks: u32,
+    pub attractors_consumed: usize,
+    /// How the game would score the run, if the raccoon won
+    pub score: Option<LevelScore>,
+}
+
+/// Reads an LDtk project such as `assets/attic.ldtk` from disk
+pub fn load_project(path: impl AsRef<Path>) -> Result<LdtkJson, SimulationError> {
+    let bytes = std::fs::read(path)?;
+    Ok(serde_json::from_slice(&bytes)?)
+}
+
+/// Reads a tuning file such as `assets/game.tuning.ron` from disk
+pub fn load_tunables(path: impl AsRef<Path>) -> Result<Tunables, SimulationError> {
+    let text = std::fs::read_to_string(path).map_err(TuningError::Io)?;
+    Ok(Tunables::from_ron(&text)?)
+}
+
+/// Reads a replay file written by the game
+pub fn load_replay(path: impl AsRef<Path>) -> Result<Replay, SimulationError> {
+    Replay::from_json(&std::fs::read_to_string(path)?).map_err(SimulationError::Replay)
+}
+
+/// A single level, ready to be stepped one tick at a time
+pub struct Simulation {
+    app: App,
+    player: Entity,
+    edible: Vec<Entity>,
+    /// Goodies the placer had before putting any down
+    start: Inventory,
+    placed: Vec<ItemKind>,
+    timestep: Duration,
+    /// Fixed steps packed into each update
+    steps_per_update: u32,
+    ticks: u32,
+}
+
+impl Simulation {
+    /// Spawns `level` from `project` with the given goodies already placed
+    pub fn new(project: &LdtkJson, level: usize, attractors: &[Placement]) -> Result<Self, SimulationError> {
+        Self::with_timestep(project, level, attractors, DEFAULT_TIMESTEP)
+    }
+
+    pub fn with_timestep(
+        project: &LdtkJson,
+        level: usize,
+        attractors: &[Placement],
+        timestep: f32,
+    ) -> Result<Self, SimulationError> {
+        let missing = missing_physics(&project.defs);
+        if !missing.is_empty() {
+            return Err(SimulationError::MissingPhysics(missing));
+        }
+        let ldtk_level = project
+            .get_raw_level_at_indices(&LevelIndices { level, ..default() })
+            .ok_or(SimulationError::MissingLevel(level))?;
+
+        let rules = LevelRules::from_level(ldtk_level);
+
+        // advance the clock by exactly one fixed step per update
+        let timestep = Duration::from_secs_f32(timestep);
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, TransformPlugin, HierarchyPlugin))
+            .add_plugins(PhysicsPlugin { timestep })
+            .insert_resource(TimeUpdateStrategy::ManualDuration(timestep))
+            .add_state::<GameState>()
+            .insert_resource(rules.clone())
+            .add_plugins(HeroPlugin);
+
+        let player = spawn_level(&mut app.world, ldtk_level)?;
+        let edible = spawn_attractors(&mut app.world, attractors);
+
+        // let rapier register the colliders before the raccoon starts looking around
+        app.update();
+        app.world.resource_mut::<NextState<GameState>>().set(GameState::Running);
+
+        Ok(Self {
+            app,
+            player,
+            edible,
+            start: rules.budget,
+            placed: attractors.iter().map(|placement| placement.kind).collect(),
+            timestep,
+            steps_per_update: 1,
+            ticks: 0,
+        })
+    }
+
+    /// Advances one update, returning the outcome once the run is decided
+    pub fn step(&mut self) -> Option<Outcome> {
+        self.app.update();
+        self.ticks += self.steps_per_update;
+        self.outcome()
+    }
+
+    pub fn outcome(&self) -> Option<Outcome> {
+        // transitions requested this tick only apply on the next one
+        let state = match self.app.world.resource::<NextState<GameState>>().0 {
+            Some(next) => next,
+            None => *self.app.world.resource::<State<GameState>>().get(),
+        };
+        match state {
+            GameState::AdvanceLevel => Some(Outcome::Win),
+            GameState::GameLose => Some(Outcome::Lose(*self.app.world.resource::<LoseCause>())),
+            _ => None,
+        }
+    }
+
+    /// Sets up the level of `replay` with the goodies that were down when its run started
+    pub fn from_replay(project: &LdtkJson, replay: &Replay) -> Result<Self, SimulationError> {
+        let level = project
+            .iter_raw_levels()
+            .position(|level| level.identifier == replay.level)
+            .ok_or_else(|| SimulationError::UnknownLevel(replay.level.clone()))?;
+        let mut simulation = Simulation::new(project, level, &replay.placements())?;
+        simulation.set_hero(replay.hero.clone());
+        simulation.start = replay.inventory.clone();
+        Ok(simulation)
+    }
+
+    /// Starts the raccoon off with progress from earlier levels
+    pub fn set_hero(&mut self, stats: HeroStats) {
+        self.app.world.entity_mut(self.player).insert(stats);
+    }
+
+    /// Runs with the numbers from a tuning file rather than the built-in defaults
+    pub fn set_tunables(&mut self, tunables: Tunables) {
+        self.app.world.insert_resource(tunables);
+    }
+
+    /// The raccoon's stats, which stay readable until the tick after it wins
+    pub fn hero(&self) -> &HeroStats {
+        self.app.world.get::<HeroStats>(self.player).expect("the raccoon has left the level")
+    }
+
+    /// Runs `steps` fixed steps in every update, the way the game does while fast-forwarding or seeking a replay
+    pub fn set_steps_per_update(&mut self, steps: u32) {
+        self.steps_per_update = steps;
+        self.app.insert_resource(TimeUpdateStrategy::ManualDuration(self.timestep * steps));
+    }
+
+    /// Fixed steps run so far
+    pub fn ticks(&self) -> u32 {
+        self.ticks
+    }
+
+    /// Attractors, placed or garbage, that the raccoon has eaten so far
+    pub fn attractors_consumed(&self) -> usize {
+        self.edible
+            .iter()
+            .filter(|e| self.app.world.get_entity(**e).is_none())
+            .count()
+    }
+
+    /// Where the raccoon currently is, if it's still around
+    pub fn player_position(&mut self) -> Option<Vec2> {
+        self.app
+            .world
+            .query_filtered::<&GlobalTransform, With<Player>>()
+            .iter(&self.app.world)
+            .next()
+            .map(|xform| xform.translation().truncate())
+    }
+
+    pub fn world(&self) -> &World {
+        &self.app.world
+    }
+
+    /// The score for reaching the goal right now, as the results screen would show it
+    ///
+    /// Placements the placer couldn't have afforded don't count against its inventory.
+    pub fn score(&self) -> LevelScore {
+        let rules = self.app.world.resource::<LevelRules>();
+        let mut left = self.start.clone();
+        for kind in &self.placed {
+            left.take(*kind);
+        }
+        LevelScore::from_placer(
+            &rules.budget,
+            &self.start,
+            &left,
+            self.app.world.resource::<RunClock>().0,
+            self.app.world.resource::<RunTally>().garbage_eaten,
+            rules.par,
+        )
+    }
+
+    /// Steps until the run is decided or `tick_limit` ticks have passed
+    pub fn run(mut self, tick_limit: u32) -> SimulationReport {
+        let mut outcome = Outcome::TickLimit;
+        while self.ticks < tick_limit {
+            if let Some(decided) = self.step() {
+                outcome = decided;
+                break;
+            }
+        }
+        SimulationReport {
+            outcome,
+            ticks: self.ticks,
+            attractors_consumed: self.attractors_consumed(),
+            score: (outcome == Outcome::Win).then(|| self.score()),
+        }
+    }
+}
+
+/// Runs `level` to completion with the given placements
+pub fn simulate(
+    project: &LdtkJson,
+    level: usize,
+    attractors: &[Placement],
+    tick_limit: u32,
+) -> Result<SimulationReport, SimulationError> {
+    Ok(Simulation::new(project, level, attractors)?.run(tick_limit))
+}
+
+/// Spawns the walls and entities of a level the same place the game would, returning the raccoon
+fn spawn_level(world: &mut World, level: &Level) -> Result<Entity, SimulationError> {
+    let level_translation = ldtk_pixel_coords_to_translation(
+        IVec2::new(level.world_x, level.world_y + level.px_hei),
+        0,
+    );
+    let level_entity = world
+        .spawn(SpatialBundle::from_transform(Transform::from_translation(level_translation.extend(0.0))))
+        .id();
+
+    let mut player = None;
+    for layer in level.layer_instances.iter().flatten() {
+        let mut walls = HashSet::new();
+        for (i, value) in layer.int_grid_csv.iter().enumerate() {
+            if *value == WALL_VALUE {
+                if let Some(coords) = int_grid_index_to_grid_coords(i, layer.c_wid as u32, layer.c_hei as u32) {
+                    walls.insert(coords);
+                }
+            }
+        }
+        for wall_rect in merge_wall_rects(&walls, layer.c_wid, layer.c_hei) {
+            world.spawn(wall_rect.collider_bundle(layer.grid_size)).set_parent(level_entity);
+        }
+        if !walls.is_empty() {
+            world
+                .entity_mut(level_entity)
+                .insert(NavGrid::new(&walls, layer.c_wid, layer.c_hei, layer.grid_size));
+        }
+
+        for instance in &layer.entity_instances {
+            // entities sit in the level, like the ones the LDtk plugin spawns
+            let translation = ldtk_pixel_coords_to_translation_pivoted(
+                instance.px,
+                level.px_hei,
+                IVec2::new(instance.width, instance.height),
+                instance.pivot,
+            );
+            let transform = TransformBundle::from_transform(Transform::from_translation(translation.extend(0.0)));
+
+            let entity = match instance.identifier.as_ref() {
+                "Raccoon" => world.spawn((
+                    Player,
+                    HeroStats::default(),
+                    Memory::default(),
+                    ColliderBundle::from(instance),
+                    AnimationTimer::default(),
+                    TextureAtlasSprite::default(),
+                    transform,
+                )),
+                "Trash" => world.spawn((Goal, Attractor::default(), transform)),
+                "Garbage" => world.spawn((Attractor::default(), transform)),
+                "Exit" => world.spawn((Exit, transform)),
+                "Baddie" => world.spawn((
+                    Baddie::from(instance),
+                    Patrol::from_instance(instance, layer),
+                    ColliderBundle::from(instance),
+                    AnimationTimer::default(),
+                    TextureAtlasSprite::default(),
+                    transform,
+                )),
+                _ => continue,
+            }
+            .set_parent(level_entity)
+            .id();
+            if instance.identifier == "Raccoon" {
+                player = Some(entity);
+            }
+        }
+    }
+
+    player.ok_or(SimulationError::MissingEntity("Raccoon"))
+}
+
+/// Spawns the placed attractors, returning every non-goal attractor in the level
+fn spawn_attractors(world: &mut World, attractors: &[Placement]) -> Vec<Entity> {
+    for placement in attractors {
+        world.spawn((
+            placement.kind.attractor(),
+            Goodie,
+            Item(placement.kind),
+            TransformBundle::from_transform(Transform::from_translation(placement.pos.extend(2.0))),
+        ));
+    }
+    world
+        .query_filtered::<Entity, (With<Attractor>, Without<Goal>)>()
+        .iter(world)
+        .collect()
+}
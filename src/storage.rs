@@ -0,0 +1,52 @@
+//! Small named documents that outlive the game window
+//!
+//! Natively each one is a JSON file under the user's data directory, with
+//! `/` in a name making subdirectories. In a browser each one is a
+//! `localStorage` entry keyed `ld55-<name>`.
+
+#[cfg(not(target_arch = "wasm32"))]
+mod native {
+    use std::path::PathBuf;
+
+    use directories::ProjectDirs;
+
+    fn path(name: &str) -> Option<PathBuf> {
+        ProjectDirs::from("", "", "ld55").map(|dirs| dirs.data_dir().join(format!("{name}.json")))
+    }
+
+    pub fn read(name: &str) -> Option<String> {
+        std::fs::read_to_string(path(name)?).ok()
+    }
+
+    pub fn write(name: &str, json: &str) -> Result<(), String> {
+        let path = path(name).ok_or("no user data directory")?;
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+        }
+        std::fs::write(&path, json).map_err(|e| format!("{}: {e}", path.display()))
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub use native::{read, write};
+
+#[cfg(target_arch = "wasm32")]
+mod web {
+    fn local_storage() -> Option<web_sys::Storage> {
+        web_sys::window()?.local_storage().ok().flatten()
+    }
+
+    pub fn read(name: &str) -> Option<String> {
+        local_storage()?.get_item(&format!("ld55-{name}")).ok().flatten()
+    }
+
+    pub fn write(name: &str, json: &str) -> Result<(), String> {
+        local_storage()
+            .ok_or("no localStorage")?
+            .set_item(&format!("ld55-{name}"), json)
+            .map_err(|e| format!("{e:?}"))
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+pub use web::{read, write};
@@ -2,12 +2,123 @@ use crate::components::*;
 use bevy::prelude::*;
 use bevy_ecs_ldtk::prelude::*;
 
+use std::cmp::min;
 use std::collections::{HashMap, HashSet};
+use std::f32::consts::TAU;
+use std::time::Duration;
+
+use bevy::render::mesh::Indices;
+use bevy::sprite::Mesh2dHandle;
 
 use bevy_rapier2d::prelude::*;
 
 use crate::components;
+use crate::events::*;
+use crate::items::ItemKind;
+use crate::nav::{nav_grid_at, Memory, NavGrid};
+use crate::tuning::Tunables;
+
+
+/// A simple rectangle type representing a wall of any size, in grid cells
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub struct WallRect {
+    pub left: i32,
+    pub right: i32,
+    pub top: i32,
+    pub bottom: i32,
+}
 
+impl WallRect {
+    /// The fixed collider for this rectangle, positioned relative to its level
+    pub fn collider_bundle(&self, grid_size: i32) -> (Collider, RigidBody, Friction, TransformBundle) {
+        (
+            Collider::cuboid(
+                (self.right as f32 - self.left as f32 + 1.) * grid_size as f32 / 2.,
+                (self.top as f32 - self.bottom as f32 + 1.) * grid_size as f32 / 2.,
+            ),
+            RigidBody::Fixed,
+            Friction::new(1.0),
+            TransformBundle::from_transform(Transform::from_xyz(
+                (self.left + self.right + 1) as f32 * grid_size as f32 / 2.,
+                (self.bottom + self.top + 1) as f32 * grid_size as f32 / 2.,
+                0.,
+            )),
+        )
+    }
+}
+
+/// Combines the wall tiles of a level into as few rectangles as reasonably possible
+///
+/// In basic terms, it will:
+/// 1. combine wall tiles into flat "plates" in each individual row
+/// 2. combine the plates into rectangles across multiple rows wherever possible
+pub fn merge_wall_rects(level_walls: &HashSet<GridCoords>, width: i32, height: i32) -> Vec<WallRect> {
+    /// Represents a wide wall that is 1 tile tall
+    /// Used to spawn wall collisions
+    #[derive(Clone, Eq, PartialEq, Debug, Default, Hash)]
+    struct Plate {
+        left: i32,
+        right: i32,
+    }
+
+    // combine wall tiles into flat "plates" in each individual row
+    let mut plate_stack: Vec<Vec<Plate>> = Vec::new();
+
+    for y in 0..height {
+        let mut row_plates: Vec<Plate> = Vec::new();
+        let mut plate_start = None;
+
+        // + 1 to the width so the algorithm "terminates" plates that touch the right edge
+        for x in 0..width + 1 {
+            match (plate_start, level_walls.contains(&GridCoords { x, y })) {
+                (Some(s), false) => {
+                    row_plates.push(Plate {
+                        left: s,
+                        right: x - 1,
+                    });
+                    plate_start = None;
+                }
+                (None, true) => plate_start = Some(x),
+                _ => (),
+            }
+        }
+
+        plate_stack.push(row_plates);
+    }
+
+    // combine "plates" into rectangles across multiple rows
+    let mut rect_builder: HashMap<Plate, WallRect> = HashMap::new();
+    let mut prev_row: Vec<Plate> = Vec::new();
+    let mut wall_rects: Vec<WallRect> = Vec::new();
+
+    // an extra empty row so the algorithm "finishes" the rects that touch the top edge
+    plate_stack.push(Vec::new());
+
+    for (y, current_row) in plate_stack.into_iter().enumerate() {
+        for prev_plate in &prev_row {
+            if !current_row.contains(prev_plate) {
+                // remove the finished rect so that the same plate in the future starts a new rect
+                if let Some(rect) = rect_builder.remove(prev_plate) {
+                    wall_rects.push(rect);
+                }
+            }
+        }
+        for plate in &current_row {
+            rect_builder
+                .entry(plate.clone())
+                .and_modify(|e| e.top += 1)
+                .or_insert(WallRect {
+                    bottom: y as i32,
+                    top: y as i32,
+                    left: plate.left,
+                    right: plate.right,
+                });
+        }
+        prev_row = current_row;
+    }
+
+    wall_rects
+}
 
 /// Spawns heron collisions for the walls of a level
 ///
@@ -18,13 +129,8 @@ use crate::components;
 /// Instead, by flagging the wall tiles and spawning the collisions later,
 /// we can minimize the amount of colliding entities.
 ///
-/// The algorithm used here is a nice compromise between simplicity, speed,
-/// and a small number of rectangle colliders.
-/// In basic terms, it will:
-/// 1. consider where the walls are
-/// 2. combine wall tiles into flat "plates" in each individual row
-/// 3. combine the plates into rectangles across multiple rows wherever possible
-/// 4. spawn colliders for each rectangle
+/// The rectangles come from [`merge_wall_rects`], which is a nice compromise
+/// between simplicity, speed, and a small number of rectangle colliders.
 pub fn spawn_wall_collision(
     mut commands: Commands,
     wall_query: Query<(&GridCoords, &Parent), Added<Wall>>,
@@ -33,22 +139,6 @@ pub fn spawn_wall_collision(
     ldtk_projects: Query<&Handle<LdtkProject>>,
     ldtk_project_assets: Res<Assets<LdtkProject>>,
 ) {
-    /// Represents a wide wall that is 1 tile tall
-    /// Used to spawn wall collisions
-    #[derive(Clone, Eq, PartialEq, Debug, Default, Hash)]
-    struct Plate {
-        left: i32,
-        right: i32,
-    }
-
-    /// A simple rectangle type representing a wall of any size
-    struct Rect {
-        left: i32,
-        right: i32,
-        top: i32,
-        bottom: i32,
-    }
-
     // Consider where the walls are
     // storing them as GridCoords in a HashSet for quick, easy lookup
     //
@@ -89,88 +179,16 @@ pub fn spawn_wall_collision(
                     ..
                 } = level.layer_instances()[0];
 
-                // combine wall tiles into flat "plates" in each individual row
-                let mut plate_stack: Vec<Vec<Plate>> = Vec::new();
-
-                for y in 0..height {
-                    let mut row_plates: Vec<Plate> = Vec::new();
-                    let mut plate_start = None;
-
-                    // + 1 to the width so the algorithm "terminates" plates that touch the right edge
-                    for x in 0..width + 1 {
-                        match (plate_start, level_walls.contains(&GridCoords { x, y })) {
-                            (Some(s), false) => {
-                                row_plates.push(Plate {
-                                    left: s,
-                                    right: x - 1,
-                                });
-                                plate_start = None;
-                            }
-                            (None, true) => plate_start = Some(x),
-                            _ => (),
-                        }
-                    }
-
-                    plate_stack.push(row_plates);
-                }
-
-                // combine "plates" into rectangles across multiple rows
-                let mut rect_builder: HashMap<Plate, Rect> = HashMap::new();
-                let mut prev_row: Vec<Plate> = Vec::new();
-                let mut wall_rects: Vec<Rect> = Vec::new();
-
-                // an extra empty row so the algorithm "finishes" the rects that touch the top edge
-                plate_stack.push(Vec::new());
-
-                for (y, current_row) in plate_stack.into_iter().enumerate() {
-                    for prev_plate in &prev_row {
-                        if !current_row.contains(prev_plate) {
-                            // remove the finished rect so that the same plate in the future starts a new rect
-                            if let Some(rect) = rect_builder.remove(prev_plate) {
-                                wall_rects.push(rect);
-                            }
-                        }
-                    }
-                    for plate in &current_row {
-                        rect_builder
-                            .entry(plate.clone())
-                            .and_modify(|e| e.top += 1)
-                            .or_insert(Rect {
-                                bottom: y as i32,
-                                top: y as i32,
-                                left: plate.left,
-                                right: plate.right,
-                            });
-                    }
-                    prev_row = current_row;
-                }
+                let wall_rects = merge_wall_rects(level_walls, width, height);
 
+                commands.entity(level_entity).insert(NavGrid::new(level_walls, width, height, grid_size));
                 commands.entity(level_entity).with_children(|level| {
                     // Spawn colliders for every rectangle..
                     // Making the collider a child of the level serves two purposes:
                     // 1. Adjusts the transforms to be relative to the level for free
                     // 2. the colliders will be despawned automatically when levels unload
                     for wall_rect in wall_rects {
-                        level
-                            .spawn_empty()
-                            .insert(Collider::cuboid(
-                                (wall_rect.right as f32 - wall_rect.left as f32 + 1.)
-                                    * grid_size as f32
-                                    / 2.,
-                                (wall_rect.top as f32 - wall_rect.bottom as f32 + 1.)
-                                    * grid_size as f32
-                                    / 2.,
-                            ))
-                            .insert(RigidBody::Fixed)
-                            .insert(Friction::new(1.0))
-                            .insert(Transform::from_xyz(
-                                (wall_rect.left + wall_rect.right + 1) as f32 * grid_size as f32
-                                    / 2.,
-                                (wall_rect.bottom + wall_rect.top + 1) as f32 * grid_size as f32
-                                    / 2.,
-                                0.,
-                            ))
-                            .insert(GlobalTransform::default());
+                        level.spawn(wall_rect.collider_bundle(grid_size));
                     }
                 });
             }
@@ -178,8 +196,9 @@ pub fn spawn_wall_collision(
     }
 }
 
+/// Keeps the camera on the raccoon outside of Planning
 pub fn camera_follow(
-    state: Res<CurrentState>,
+    tunables: Res<Tunables>,
     player: Query<&GlobalTransform, With<components::Player>>,
     mut camera: Query<(&mut Transform, &mut OrthographicProjection), (With<components::MainCamera>, Without<components::Player>)>,
 ) {
@@ -187,12 +206,10 @@ pub fn camera_follow(
         return;
     }
 
-    if state.0 != GameState::Planning {
-        if let Some(player) = player.iter().next() {
-            let (mut camera_xform, mut camera_proj) = camera.single_mut();
-            camera_xform.translation = player.translation();
-            camera_proj.scale = 0.25;
-        }
+    if let Some(player) = player.iter().next() {
+        let (mut camera_xform, mut camera_proj) = camera.single_mut();
+        camera_xform.translation = player.translation();
+        camera_proj.scale = tunables.follow_zoom;
     }
 }
 
@@ -212,3 +229,601 @@ pub fn mouse_to_world(
             .map(|r| r.origin);
     }
 }
+
+/// True when nothing static sits on the straight line between `from` and `to`
+pub fn line_of_sight(rapier: &RapierContext, from: Vec2, to: Vec2) -> bool {
+    let filter = QueryFilter::exclude_dynamic();
+    rapier.cast_ray(from, to - from, 1.0, false, filter).is_none()
+}
+
+/// How much the raccoon wants the attractor at `at`, or `None` if it's out of range or out of sight
+///
+/// Within `reach` it smells the attractor whichever way it's facing.
+pub fn attractor_score(
+    rapier: &RapierContext,
+    vision: &Vision,
+    reach: f32,
+    from: Vec2,
+    facing: Vec2,
+    attractor: &Attractor,
+    at: Vec2,
+) -> Option<f32> {
+    let distance = from.distance(at);
+    if distance > attractor.radius {
+        return None;
+    }
+
+    // right under its nose the raccoon can smell what it can't see
+    if distance >= reach && !vision.covers(facing, at - from) {
+        return None;
+    }
+
+    // verify that the ray doesn't collide with something else first
+    if !line_of_sight(rapier, from, at) {
+        return None;
+    }
+
+    // between equally tempting attractors the closest one wins
+    Some(attractor.priority / distance.max(1.0))
+}
+
+/// Steps per second that pulls, speeds and damping were tuned at
+const TUNED_RATE: f32 = 60.0;
+
+/// How much speed a walker keeps over a step of `dt` seconds, given what it keeps each step at [`TUNED_RATE`]
+pub fn damping(dt: f32, per_step: f32) -> f32 {
+    per_step.powf(dt * TUNED_RATE)
+}
+
+/// Turns a pull or speed, tuned as velocity added per step at [`TUNED_RATE`], into the velocity to add over `dt` seconds
+pub fn impulse(pull: f32, dt: f32) -> f32 {
+    pull * dt * TUNED_RATE
+}
+
+/// Slows everything that walks down a little every step, whatever it's doing
+pub fn damp_movement(
+    time: Res<Time>,
+    tunables: Res<Tunables>,
+    mut walkers: Query<&mut Velocity, Or<(With<Player>, With<Baddie>)>>,
+) {
+    for mut vel in walkers.iter_mut() {
+        vel.linvel *= damping(time.delta_seconds(), tunables.damping);
+    }
+}
+
+/// Pulls the raccoon toward the most tempting attractor it can see, eating the ones it reaches
+///
+/// With nothing in view it heads for whatever it saw most recently instead.
+pub fn update_player(
+    time: Res<Time>,
+    mut eaten: EventWriter<AttractorEaten>,
+    mut stalled: EventWriter<HeroStalled>,
+    mut died: EventWriter<HeroDied>,
+    mut player: Query<(Entity, &mut HeroStats, &mut Velocity, &mut Memory, &GlobalTransform, Has<Stalled>), With<Player>>,
+    attractors: Query<(Entity, &Attractor, &GlobalTransform, Has<Goodie>), Without<Player>>,
+    goal: Query<(Entity, &Transform), (With<Goal>, Without<Player>)>,
+    grids: Query<(&NavGrid, &GlobalTransform)>,
+    rules: Res<LevelRules>,
+    tunables: Res<Tunables>,
+    rapier: Res<RapierContext>,
+    mut commands: Commands,
+) {
+    for (p_entity, mut stats, mut p_vel, mut memory, p_pos, was_stalled) in player.iter_mut() {
+        memory.tick(time.delta_seconds(), tunables.memory_span);
+        if stats.hunger_pang(time.delta_seconds(), &rules) {
+            died.send(HeroDied { cause: LoseCause::Starved });
+            return;
+        }
+
+        // find the most appealing attractor and move towards it
+        let mut best_score = 0.0;
+        let mut best = None;
+
+        for (e_attr, attractor, p_attr, placed) in attractors.iter() {
+            let to_attr = (p_attr.translation() - p_pos.translation()).truncate();
+            let from = p_pos.translation().truncate();
+            let facing = p_pos.up().truncate();
+            let Some(score) = attractor_score(&rapier, &rules.vision, tunables.reach_distance, from, facing, attractor, p_attr.translation().truncate()) else {
+                continue;
+            };
+            memory.see(p_attr.translation().truncate(), attractor.pull);
+            if score > best_score {
+                best_score = score;
+                best = Some((to_attr, attractor.pull));
+            }
+
+            // if the attractor is in range and not the goal then collect it
+            if to_attr.length() < tunables.reach_distance && goal.get(e_attr).is_err() {
+                commands.entity(e_attr).despawn();
+                stats.eat(tunables.eat_refill);
+                eaten.send(AttractorEaten { at: p_attr.translation().truncate(), placed });
+                memory.forget_at(p_attr.translation().truncate());
+            }
+        }
+        let from = p_pos.translation().truncate();
+        let nav = if rules.pathfinding { nav_grid_at(&grids, from) } else { None };
+        if let Some((to_attr, pull)) = best {
+            p_vel.linvel += to_attr.normalize_or_zero() * impulse(pull, time.delta_seconds());
+        } else if let Some((waypoint, pull)) = memory.next_waypoint(&rapier, tunables.reach_distance, from, nav) {
+            // out of sight, but not out of mind
+            p_vel.linvel += (waypoint - from).normalize_or_zero() * impulse(pull, time.delta_seconds());
+        } else {
+            if !was_stalled {
+                stalled.send(HeroStalled { at: from });
+                commands.entity(p_entity).insert(Stalled);
+            }
+            continue;
+        }
+        if was_stalled {
+            commands.entity(p_entity).remove::<Stalled>();
+        }
+    }
+}
+
+/// Counts the level's own garbage the raccoon eats, for the score
+pub fn tally_garbage(
+    mut eaten: EventReader<AttractorEaten>,
+    mut tally: ResMut<RunTally>,
+) {
+    for event in eaten.read() {
+        if !event.placed {
+            tally.garbage_eaten += 1;
+        }
+    }
+}
+
+/// Moves the game on when a run is won or lost, or the raccoon leaves through the exit
+///
+/// Losing beats winning if both happen on the same frame.
+pub fn finish_run(
+    mut completed: EventReader<LevelCompleted>,
+    mut died: EventReader<HeroDied>,
+    mut exited: EventReader<ExitReached>,
+    mut lose_cause: ResMut<LoseCause>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if let Some(death) = died.read().last() {
+        *lose_cause = death.cause;
+        next_state.set(GameState::GameLose);
+    } else if completed.read().last().is_some() {
+        next_state.set(GameState::AdvanceLevel);
+    } else if exited.read().last().is_some() {
+        next_state.set(GameState::WinDance);
+    }
+    // whatever was left over is stale once the state has moved on
+    completed.clear();
+    exited.clear();
+}
+
+/// Where the raccoon is expected to go, from [`predict_path`]
+#[derive(Clone, Debug, Default)]
+pub struct PredictedPath {
+    pub points: Vec<Vec2>,
+    pub reaches_goal: bool,
+}
+
+/// Traces the raccoon's path from `start` by the same rules as [`update_player`], without touching the world
+///
+/// Each attractor comes with its position and whether it's the goal. Pass `nav` to follow remembered
+/// attractors around walls the way pathfinding levels do. The raccoon starts on a full hunger meter,
+/// as it does at the start of every level. Walls come from `rapier`, but
+/// baddies and the raccoon's own size are ignored, so the path is a guide rather than a promise.
+pub fn predict_path(
+    rapier: &RapierContext,
+    rules: &LevelRules,
+    tunables: &Tunables,
+    nav: Option<(&NavGrid, Vec2)>,
+    start: Vec2,
+    start_facing: Vec2,
+    attractors: &[(Attractor, Vec2, bool)],
+    timestep: f32,
+    max_steps: usize,
+) -> PredictedPath {
+    let mut remaining = attractors.to_vec();
+    let mut pos = start;
+    let mut vel = Vec2::ZERO;
+    let mut facing = start_facing;
+    let mut memory = Memory::default();
+    let mut stats = HeroStats::default();
+    let mut points = vec![start];
+
+    for _ in 0..max_steps {
+        // same order as the game: damp, pull, then let physics move
+        vel *= damping(timestep, tunables.damping);
+        memory.tick(timestep, tunables.memory_span);
+        if stats.hunger_pang(timestep, rules) {
+            break;
+        }
+        let mut best_score = 0.0;
+        let mut best = None;
+        let mut eaten = Vec::new();
+        for (i, (attractor, at, is_goal)) in remaining.iter().enumerate() {
+            let Some(score) = attractor_score(rapier, &rules.vision, tunables.reach_distance, pos, facing, attractor, *at) else {
+                continue;
+            };
+            memory.see(*at, attractor.pull);
+            if score > best_score {
+                best_score = score;
+                best = Some((*at - pos, attractor.pull));
+            }
+            if pos.distance(*at) < tunables.reach_distance && !is_goal {
+                eaten.push(i);
+            }
+        }
+        for i in eaten.into_iter().rev() {
+            memory.forget_at(remaining.remove(i).1);
+            stats.eat(tunables.eat_refill);
+        }
+
+        if let Some((to_attr, pull)) = best {
+            vel += to_attr.normalize_or_zero() * impulse(pull, timestep);
+        } else if let Some((waypoint, pull)) = memory.next_waypoint(rapier, tunables.reach_distance, pos, nav) {
+            vel += (waypoint - pos).normalize_or_zero() * impulse(pull, timestep);
+        }
+        pos = slide(rapier, pos, vel * timestep, &mut vel);
+        points.push(pos);
+        // face_movement only turns the raccoon once it's properly moving
+        if vel.length() > tunables.walk_speed {
+            facing = vel.normalize();
+        }
+
+        if remaining.iter().any(|(_, at, is_goal)| *is_goal && pos.distance(*at) < tunables.reach_distance) {
+            return PredictedPath { points, reaches_goal: true };
+        }
+    }
+    PredictedPath { points, reaches_goal: false }
+}
+
+/// Moves `pos` by `delta` unless a wall is in the way, sliding along it when only one axis is blocked
+fn slide(rapier: &RapierContext, pos: Vec2, delta: Vec2, vel: &mut Vec2) -> Vec2 {
+    if delta == Vec2::ZERO || line_of_sight(rapier, pos, pos + delta) {
+        return pos + delta;
+    }
+    let mut moved = pos;
+    for axis in [Vec2::X, Vec2::Y] {
+        let step = delta * axis;
+        if step != Vec2::ZERO && line_of_sight(rapier, moved, moved + step) {
+            moved += step;
+        } else {
+            *vel *= Vec2::ONE - axis;
+        }
+    }
+    moved
+}
+
+/// Walks the raccoon out through the exit once the game is won
+pub fn walk_to_exit(
+    time: Res<Time>,
+    tunables: Res<Tunables>,
+    mut exited: EventWriter<ExitReached>,
+    mut player: Query<(Entity, &mut Velocity, &GlobalTransform), With<Player>>,
+    exit: Query<&GlobalTransform, With<Exit>>,
+    mut commands: Commands,
+) {
+    for (pentity, mut p_vel, p_pos) in player.iter_mut() {
+        for e_exit in exit.iter() {
+            let to_exit = (e_exit.translation() - p_pos.translation()).truncate();
+            p_vel.linvel += to_exit.normalize_or_zero() * impulse(tunables.exit_pull, time.delta_seconds());
+            if to_exit.length() < tunables.reach_distance {
+                exited.send(ExitReached);
+                commands.entity(pentity).despawn();
+            }
+        }
+    }
+}
+
+pub fn animate_death(
+    time: Res<Time>,
+    mut player: Query<(&mut TextureAtlasSprite, &mut LevelEndTimer), With<Player>>,
+) {
+    for (mut atlas, mut death_timer) in player.iter_mut() {
+        // advance the goal animation linearly through time
+        death_timer.0.tick(time.delta());
+        let remaining = death_timer.0.remaining_secs();
+        let since_start = death_timer.0.duration().as_secs_f32() - remaining;
+        let completion_fraction = since_start / death_timer.0.duration().as_secs_f32();
+
+        // animation is in frame 5-8
+        let frame = (completion_fraction * 4.0) as usize;
+        atlas.index = min(8, 4 + frame);
+    }
+}
+
+/// Faces the walker the way it's moving
+///
+/// The raccoon looks where it faces, so this runs every fixed step rather than every frame.
+pub fn face_movement(
+    tunables: Res<Tunables>,
+    mut walkers: Query<(&Velocity, &mut Transform), Or<(With<Player>, With<Baddie>)>>,
+) {
+    for (p_vel, mut p_xform) in walkers.iter_mut() {
+        if p_vel.linvel.length() > tunables.walk_speed {
+            // the sprite native orientation is straight up. orient it in the direction of the velocity vector
+            let angle = p_vel.linvel.angle_between(Vec2::Y);
+            p_xform.rotation = Quat::from_rotation_z(-angle);
+        }
+    }
+}
+
+/// Keeps every animation at the tuned frame length, also after the tuning file changes
+pub fn time_animations(
+    tunables: Res<Tunables>,
+    mut timers: Query<&mut AnimationTimer>,
+) {
+    let frame = Duration::from_secs_f32(tunables.animation_frame_seconds);
+    for mut timer in timers.iter_mut() {
+        if timer.0.duration() != frame {
+            timer.0.set_duration(frame);
+        }
+    }
+}
+
+/// Plays the walk cycle while the walker is moving
+pub fn animate_walk(
+    time: Res<Time>,
+    tunables: Res<Tunables>,
+    mut walkers: Query<(&Velocity, &mut AnimationTimer, &mut TextureAtlasSprite), Or<(With<Player>, With<Baddie>)>>,
+) {
+    for (p_vel, mut timer, mut atlas) in walkers.iter_mut() {
+        if p_vel.linvel.length() > tunables.walk_speed && timer.0.tick(time.delta()).just_finished() {
+            atlas.index = (atlas.index + 1) % 4;
+        }
+    }
+}
+
+/// Marks every attractor the raccoon can currently see, when the overlay asks for it
+pub fn draw_sight_lines(
+    overlay: Res<SightOverlay>,
+    rules: Res<LevelRules>,
+    tunables: Res<Tunables>,
+    player: Query<&GlobalTransform, With<Player>>,
+    attractors: Query<(&Attractor, &GlobalTransform), Without<Player>>,
+    rapier: Res<RapierContext>,
+    mut gizmos: Gizmos,
+) {
+    if !overlay.show_visible {
+        return;
+    }
+    let color = Color::rgba(1.0, 0.95, 0.6, 0.5);
+    for p_pos in player.iter() {
+        for (attractor, p_attr) in attractors.iter() {
+            let from = p_pos.translation().truncate();
+            let to = p_attr.translation().truncate();
+            if attractor_score(&rapier, &rules.vision, tunables.reach_distance, from, p_pos.up().truncate(), attractor, to).is_some() {
+                gizmos.line_2d(from, to, color);
+                gizmos.circle_2d(to, 8.0, color);
+            }
+        }
+    }
+}
+
+/// Marks where the raccoon remembers attractors it can't see any more, fading as it forgets
+pub fn draw_memories(
+    player: Query<&Memory, With<Player>>,
+    tunables: Res<Tunables>,
+    mut gizmos: Gizmos,
+) {
+    for memory in player.iter() {
+        for sighting in &memory.sightings {
+            // anything fresh is still in view and already marked
+            if sighting.age > 0.0 {
+                let fade = 1.0 - sighting.age / tunables.memory_span;
+                gizmos.circle_2d(sighting.at, 6.0, Color::rgba(0.7, 0.8, 1.0, 0.8 * fade));
+            }
+        }
+    }
+}
+
+/// The outline of the region visible from `origin` out to `range`, in order of angle across the raccoon's view
+///
+/// Rays go out at regular intervals and just either side of every wall corner, so the outline hugs
+/// the merged wall rectangles without casting against every tile.
+pub fn visibility_polygon(
+    rapier: &RapierContext,
+    vision: &Vision,
+    range: f32,
+    origin: Vec2,
+    facing: Vec2,
+    corners: &[Vec2],
+) -> Vec<Vec2> {
+    const SWEEP_RAYS: usize = 64;
+    const CORNER_NUDGE: f32 = 0.001;
+
+    let range = vision.radius.min(range);
+    // measured from the way the raccoon faces, so a view cone is one unbroken span
+    let span = vision.cone.unwrap_or(TAU);
+    let mut angles: Vec<f32> = (0..=SWEEP_RAYS)
+        .map(|i| i as f32 / SWEEP_RAYS as f32 * span - span / 2.0)
+        .collect();
+    for corner in corners {
+        let to_corner = *corner - origin;
+        if to_corner != Vec2::ZERO && to_corner.length() <= range {
+            let angle = facing.angle_between(to_corner);
+            angles.extend(
+                [angle - CORNER_NUDGE, angle, angle + CORNER_NUDGE]
+                    .into_iter()
+                    .filter(|angle| angle.abs() <= span / 2.0),
+            );
+        }
+    }
+    angles.sort_by(f32::total_cmp);
+
+    let facing_angle = facing.y.atan2(facing.x);
+    let filter = QueryFilter::exclude_dynamic();
+    angles
+        .into_iter()
+        .map(|angle| {
+            let dir = Vec2::from_angle(facing_angle + angle);
+            let toi = rapier.cast_ray(origin, dir, range, true, filter).map_or(range, |(_, toi)| toi);
+            origin + dir * toi
+        })
+        .collect()
+}
+
+/// Reshapes the lit region around the raccoon to match what it can see
+pub fn update_sight_region(
+    rules: Res<LevelRules>,
+    tunables: Res<Tunables>,
+    player: Query<&GlobalTransform, With<Player>>,
+    walls: Query<(&Collider, &RigidBody, &GlobalTransform)>,
+    rapier: Res<RapierContext>,
+    mut region: Query<(&Mesh2dHandle, &mut Transform, &mut Visibility), With<SightRegion>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+) {
+    let Some((mesh, mut xform, mut visibility)) = region.iter_mut().next() else {
+        return;
+    };
+    let Some(p_pos) = player.iter().next() else {
+        *visibility = Visibility::Hidden;
+        return;
+    };
+    *visibility = Visibility::Inherited;
+
+    // the merged walls are the only fixed boxes around
+    let mut corners = Vec::new();
+    for (collider, body, w_pos) in walls.iter() {
+        if let (RigidBody::Fixed, Some(cuboid)) = (body, collider.as_cuboid()) {
+            let center = w_pos.translation().truncate();
+            let half = cuboid.half_extents();
+            for sign in [Vec2::new(-1.0, -1.0), Vec2::new(1.0, -1.0), Vec2::new(1.0, 1.0), Vec2::new(-1.0, 1.0)] {
+                corners.push(center + half * sign);
+            }
+        }
+    }
+
+    let origin = p_pos.translation().truncate();
+    let outline = visibility_polygon(&rapier, &rules.vision, tunables.sight_range, origin, p_pos.up().truncate(), &corners);
+
+    // a fan of triangles around the raccoon, which sits at the mesh origin, closed unless it's a cone
+    let mut positions = vec![[0.0, 0.0, 0.0]];
+    positions.extend(outline.iter().map(|point| (*point - origin).extend(0.0).to_array()));
+    let count = outline.len() as u32;
+    let triangles = if rules.vision.cone.is_some() { count - 1 } else { count };
+    let indices: Vec<u32> = (0..triangles).flat_map(|i| [0, 1 + i, 1 + (i + 1) % count]).collect();
+
+    xform.translation = origin.extend(xform.translation.z);
+    if let Some(mesh) = meshes.get_mut(&mesh.0) {
+        mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+        mesh.set_indices(Some(Indices::U32(indices)));
+    }
+}
+
+/// Baddies wear the raccoon sprite, so tell them apart by color
+pub fn tint_baddies(
+    mut baddies: Query<&mut TextureAtlasSprite, Added<Baddie>>,
+) {
+    for mut sprite in baddies.iter_mut() {
+        sprite.color = Color::rgb(0.6, 0.4, 0.9);
+    }
+}
+
+/// Chases the raccoon when it's close and in sight, otherwise keeps walking the patrol
+pub fn update_baddies(
+    time: Res<Time>,
+    tunables: Res<Tunables>,
+    player: Query<&GlobalTransform, With<Player>>,
+    mut baddies: Query<(&Baddie, &mut Patrol, &mut Velocity, &Transform, &GlobalTransform), Without<Player>>,
+    rapier: Res<RapierContext>,
+) {
+    let player = player.iter().next().map(|p| p.translation().truncate());
+    for (baddie, mut patrol, mut vel, b_xform, b_pos) in baddies.iter_mut() {
+        let b_pos = b_pos.translation().truncate();
+        let chase = player.filter(|p_pos| {
+            p_pos.distance(b_pos) < baddie.sight(&tunables) && line_of_sight(&rapier, b_pos, *p_pos)
+        });
+
+        let direction = if let Some(p_pos) = chase {
+            p_pos - b_pos
+        } else if let Some(point) = patrol.points.get(patrol.index).copied() {
+            // patrol points are relative to the level, just like the baddie's own transform
+            let to_point = point - b_xform.translation.truncate();
+            if to_point.length() < tunables.patrol_arrival_distance {
+                patrol.advance();
+            }
+            to_point
+        } else {
+            Vec2::ZERO
+        };
+        vel.linvel += direction.normalize_or_zero() * impulse(baddie.speed(&tunables), time.delta_seconds());
+    }
+}
+
+/// Settles the fight with any baddie that gets its paws on the raccoon
+///
+/// A win drives the baddie off and drops loot into the placer's inventory,
+/// a loss ends the run.
+pub fn fight_baddies(
+    mut fought: EventWriter<BaddieFought>,
+    mut died: EventWriter<HeroDied>,
+    tunables: Res<Tunables>,
+    mut player: Query<(&mut HeroStats, &GlobalTransform), With<Player>>,
+    baddies: Query<(Entity, &Baddie, &GlobalTransform)>,
+    mut commands: Commands,
+) {
+    for (mut stats, p_pos) in player.iter_mut() {
+        for (b_entity, baddie, b_pos) in baddies.iter() {
+            if p_pos.translation().truncate().distance(b_pos.translation().truncate()) >= tunables.catch_distance {
+                continue;
+            }
+            let result = stats.fight(baddie.level);
+            fought.send(BaddieFought { baddie_level: baddie.level, result });
+            match result {
+                FightResult::Won { loot } => {
+                    commands.entity(b_entity).despawn();
+                    let kind = ItemKind::best_unlocked(stats.loot_tier());
+                    stats.loot.add(kind, loot);
+                }
+                FightResult::Died => {
+                    died.send(HeroDied { cause: LoseCause::Caught });
+                    return;
+                }
+            }
+        }
+    }
+}
+
+/// Ends the run once the level's time limit has passed
+pub fn check_time_limit(
+    time: Res<Time>,
+    rules: Res<LevelRules>,
+    mut clock: ResMut<RunClock>,
+    mut died: EventWriter<HeroDied>,
+) {
+    clock.0 += time.delta_seconds();
+    if let Some(time_limit) = rules.time_limit {
+        if clock.0 > time_limit {
+            died.send(HeroDied { cause: LoseCause::TimeUp });
+        }
+    }
+}
+
+pub fn reset_run_clock(mut clock: ResMut<RunClock>) {
+    clock.0 = 0.0;
+}
+
+pub fn reset_run_tally(mut tally: ResMut<RunTally>) {
+    *tally = RunTally::default();
+}
+
+/// Every run starts on a full stomach, so the path preview can plan for it
+pub fn fill_hunger(mut heroes: Query<&mut HeroStats, With<Player>>) {
+    for mut stats in heroes.iter_mut() {
+        stats.hunger = 1.0;
+    }
+}
+
+pub fn check_win(
+    player: Query<&Transform, With<Player>>,
+    goal: Query<&Transform, (With<Goal>, Without<Player>)>,
+    tunables: Res<Tunables>,
+    mut completed: EventWriter<LevelCompleted>,
+) {
+    if let Some(player) = player.iter().next() {
+        for goal in goal.iter() {
+            let distance = player.translation.distance(goal.translation);
+            if distance < tunables.reach_distance {
+                completed.send(LevelCompleted);
+            }
+        }
+    }
+}
@@ -0,0 +1,183 @@
+//! Gameplay numbers that designers can change without recompiling
+//!
+//! [`Tunables`] is read from `assets/game.tuning.ron` through the
+//! [`AssetServer`] and copied into a resource of the same type whenever the
+//! file loads or changes. Build with `--features hot_reload` to have Bevy
+//! watch the file while the game runs. Until the file has loaded, and in the
+//! headless simulator unless it's handed one, the defaults below apply; they
+//! match the shipped file.
+
+use std::fmt;
+
+use bevy::asset::{io::Reader, AssetLoader, AsyncReadExt, LoadContext};
+use bevy::prelude::*;
+use bevy::utils::BoxedFuture;
+use serde::Deserialize;
+
+pub const TUNING_PATH: &str = "game.tuning.ron";
+
+#[derive(Asset, TypePath, Resource, Clone, PartialEq, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Tunables {
+    /// Bumped whenever the numbers change, so recordings can tell which ones they were made with
+    pub version: u32,
+    /// Fraction of its speed a walker keeps each step at 60 steps a second
+    pub damping: f32,
+    /// How hard the raccoon heads for the exit once the game is won, per step at 60 steps a second
+    pub exit_pull: f32,
+    /// How close the raccoon has to get to an attractor to eat it, or to the goal to finish the level
+    pub reach_distance: f32,
+    /// How close a baddie has to get to the raccoon to catch it
+    pub catch_distance: f32,
+    /// How close a baddie has to get to a patrol point before heading for the next one
+    pub patrol_arrival_distance: f32,
+    /// Pull a baddie puts toward where it's heading, per step at 60 steps a second, unless its `Speed` field says otherwise
+    pub baddie_speed: f32,
+    /// How close, in pixels, the raccoon has to be for a baddie to spot it, unless its `Sight` field says otherwise
+    pub baddie_sight: f32,
+    /// Speed, in pixels a second, above which a walker plays its walk cycle and turns to face where it's going
+    pub walk_speed: f32,
+    /// Share of the hunger meter each attractor eaten fills back up
+    pub eat_refill: f32,
+    /// Seconds the raccoon remembers an attractor after losing sight of it
+    pub memory_span: f32,
+    /// How close to a goodie a right-click has to land to pick it up
+    pub pick_up_radius: f32,
+    /// Pixels a second the camera pans while planning
+    pub camera_speed: f32,
+    /// Camera projection scale while planning
+    pub planning_zoom: f32,
+    /// Camera projection scale while following the raccoon
+    pub follow_zoom: f32,
+    /// How far the lit sight region reaches, in pixels
+    pub sight_range: f32,
+    /// Seconds each frame of the walk cycle and exit animations stays up
+    pub animation_frame_seconds: f32,
+    /// Seconds the goal and death animations play before the game moves on
+    pub level_end_seconds: f32,
+}
+
+impl Default for Tunables {
+    fn default() -> Self {
+        Tunables {
+            version: 1,
+            damping: 0.9,
+            exit_pull: 10.0,
+            reach_distance: 10.0,
+            catch_distance: 16.0,
+            patrol_arrival_distance: 4.0,
+            baddie_speed: 6.0,
+            baddie_sight: 96.0,
+            walk_speed: 10.0,
+            eat_refill: 0.25,
+            memory_span: 4.0,
+            pick_up_radius: 12.0,
+            camera_speed: 200.0,
+            planning_zoom: 0.5,
+            follow_zoom: 0.25,
+            sight_range: 512.0,
+            animation_frame_seconds: 0.1,
+            level_end_seconds: 1.0,
+        }
+    }
+}
+
+impl Tunables {
+    pub fn from_ron(text: &str) -> Result<Self, TuningError> {
+        Ok(ron::de::from_str(text)?)
+    }
+}
+
+#[derive(Debug)]
+pub enum TuningError {
+    Io(std::io::Error),
+    Parse(ron::error::SpannedError),
+}
+
+impl fmt::Display for TuningError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TuningError::Io(e) => write!(f, "could not read tuning file: {e}"),
+            TuningError::Parse(e) => write!(f, "could not parse tuning file: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for TuningError {}
+
+impl From<std::io::Error> for TuningError {
+    fn from(e: std::io::Error) -> Self {
+        TuningError::Io(e)
+    }
+}
+
+impl From<ron::error::SpannedError> for TuningError {
+    fn from(e: ron::error::SpannedError) -> Self {
+        TuningError::Parse(e)
+    }
+}
+
+#[derive(Default)]
+struct TunablesLoader;
+
+impl AssetLoader for TunablesLoader {
+    type Asset = Tunables;
+    type Settings = ();
+    type Error = TuningError;
+
+    fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader,
+        _settings: &'a (),
+        _load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<Tunables, TuningError>> {
+        Box::pin(async move {
+            let mut text = String::new();
+            reader.read_to_string(&mut text).await?;
+            Tunables::from_ron(&text)
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["tuning.ron"]
+    }
+}
+
+/// Keeps the handle so the tuning file stays loaded and can be watched
+#[derive(Resource)]
+struct TuningHandle(#[allow(dead_code)] Handle<Tunables>);
+
+/// Loads the tuning file and keeps the [`Tunables`] resource in step with it
+pub struct TuningPlugin;
+
+impl Plugin for TuningPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Tunables>()
+            .init_asset::<Tunables>()
+            .init_asset_loader::<TunablesLoader>()
+            .add_systems(Startup, load_tuning)
+            .add_systems(PreUpdate, apply_tuning);
+    }
+}
+
+fn load_tuning(
+    asset_server: Res<AssetServer>,
+    mut commands: Commands,
+) {
+    commands.insert_resource(TuningHandle(asset_server.load(TUNING_PATH)));
+}
+
+fn apply_tuning(
+    mut events: EventReader<AssetEvent<Tunables>>,
+    assets: Res<Assets<Tunables>>,
+    mut tunables: ResMut<Tunables>,
+) {
+    for event in events.read() {
+        if let AssetEvent::LoadedWithDependencies { id } | AssetEvent::Modified { id } = event {
+            if let Some(loaded) = assets.get(*id) {
+                info!("applying tuning version {}", loaded.version);
+                *tunables = loaded.clone();
+            }
+        }
+    }
+}
@@ -0,0 +1,88 @@
+//! Regression checks for the raccoon's behaviour, run headlessly on the shipped levels
+
+use bevy::math::{Vec2, Vec3};
+use ld55::components::{HeroStats, Inventory, LoseCause, PlacementAction};
+use ld55::items::ItemKind;
+use ld55::replay::{Replay, TimedAction, REPLAY_FORMAT};
+use ld55::sim::{self, Outcome, Simulation};
+
+const PROJECT: &str = "assets/attic.ldtk";
+const TICK_LIMIT: u32 = 60 * 60;
+
+#[test]
+fn hot_dog_by_the_trash_wins_level_0() {
+    let project = sim::load_project(PROJECT).unwrap();
+    let report = Simulation::new(&project, 0, &[Vec2::new(150.0, -279.0).into()])
+        .unwrap()
+        .run(TICK_LIMIT);
+    assert_eq!(report.outcome, Outcome::Win);
+    // about four seconds; the bound only catches the raccoon dawdling
+    assert!(report.ticks < 10 * 60, "took {} ticks", report.ticks);
+    assert_eq!(report.attractors_consumed, 1);
+}
+
+#[test]
+fn raccoon_fights_past_the_baddie_guarding_level_2() {
+    let project = sim::load_project(PROJECT).unwrap();
+    let mut simulation = Simulation::new(
+        &project,
+        2,
+        &[Vec2::new(231.0, -311.0).into(), Vec2::new(327.0, -231.0).into()],
+    )
+    .unwrap();
+    let mut outcome = None;
+    while outcome.is_none() && simulation.ticks() < TICK_LIMIT {
+        outcome = simulation.step();
+    }
+    assert_eq!(outcome, Some(Outcome::Win));
+    // beating the level 2 baddie earns loot and enough experience to level up
+    assert_eq!(simulation.hero().level, 2);
+    assert!(simulation.hero().loot.total() > 0);
+}
+
+#[test]
+fn raccoon_starves_after_level_1_hunger_time() {
+    let project = sim::load_project(PROJECT).unwrap();
+    let report = Simulation::new(&project, 1, &[]).unwrap().run(TICK_LIMIT);
+    assert_eq!(report.outcome, Outcome::Lose(LoseCause::Starved));
+    // the level's HungerTime is 8 seconds, give or take a tick or two of float drift
+    assert!((8 * 60 - 2..=8 * 60 + 2).contains(&report.ticks), "starved after {} ticks", report.ticks);
+}
+
+#[test]
+fn replay_plays_out_the_same_when_fast_forwarded() {
+    let project = sim::load_project(PROJECT).unwrap();
+    // level 2 has a view cone, so the raccoon has to turn the same way at either speed
+    let replay = Replay {
+        format: REPLAY_FORMAT,
+        level: "Level_2".to_string(),
+        tunables_version: 1,
+        seed: 0,
+        inventory: Inventory::new(&[(ItemKind::HotDog, 2)]),
+        hero: HeroStats::default(),
+        actions: [Vec3::new(231.0, -311.0, 2.0), Vec3::new(327.0, -231.0, 2.0)]
+            .into_iter()
+            .map(|pos| TimedAction { time: 0.0, action: PlacementAction::Place { pos, kind: ItemKind::HotDog } })
+            .collect(),
+        started: 0.0,
+    };
+    let play = |steps_per_update| {
+        let mut simulation = Simulation::from_replay(&project, &replay).unwrap();
+        simulation.set_steps_per_update(steps_per_update);
+        let mut outcome = None;
+        while outcome.is_none() && simulation.ticks() < TICK_LIMIT {
+            outcome = simulation.step();
+        }
+        (outcome, simulation.ticks(), simulation.attractors_consumed(), simulation.hero().clone())
+    };
+
+    let (outcome, ticks, consumed, hero) = play(1);
+    let (fast_outcome, fast_ticks, fast_consumed, fast_hero) = play(4);
+    assert_eq!(outcome, Some(Outcome::Win));
+    assert_eq!(fast_outcome, outcome);
+    assert_eq!(fast_consumed, consumed);
+    // the outcome is only noticed at the end of an update, so packing steps can overshoot by up to three,
+    // which the hunger meter keeps draining through
+    assert!((ticks..ticks + 4).contains(&fast_ticks), "{fast_ticks} ticks fast-forwarded against {ticks}");
+    assert_eq!(HeroStats { hunger: hero.hunger, ..fast_hero }, hero);
+}